@@ -1,2 +1,2 @@
-pub use macros_macros::parser;
+pub use macros_macros::{parser, Parse};
 pub use macros_utils::*;
@@ -0,0 +1,82 @@
+//! Checks bounded repetitions (like `{...}{n,m}`).
+
+mod common;
+
+use common::{parse, stream, texts};
+use macros_core::{parser, DynamicOutput, MacrosError, Parse, Pattern};
+
+const BOUNDED: &str = "{ { {}int : numbers : u8 }@ }{1,3} ;";
+
+parser! {
+    Bounded => { { {}int : numbers : u8 }@ }{1,3} ;
+}
+
+const LAZY: &str = "{ { {}$ : items : String }@ }{,3} ;";
+
+parser! {
+    Lazy => { { {}$ : items : String }@ }{,3} ;
+}
+
+const GREEDY: &str = "{ { {}$ : items }@ }{{,3}} ;";
+
+parser! {
+    Greedy => { { {}$ : items }@ }{{,3}} ;
+}
+
+/// Get the column of the span of an error.
+fn column(error: MacrosError) -> usize {
+    match error {
+        MacrosError::Parse(error) => error.span.start().column,
+        error => panic!("expected a parse error, found {:?}", error),
+    }
+}
+
+#[test]
+fn bounded() {
+    let (generated, runtime) = parse::<Bounded>(BOUNDED, "1 2 ;").unwrap();
+    assert_eq!(generated.numbers, vec![1, 2]);
+    assert_eq!(texts(runtime.get_all("numbers")), vec!["1", "2"]);
+    let (generated, _) = parse::<Bounded>(BOUNDED, "1 2 3 ;").unwrap();
+    assert_eq!(generated.numbers, vec![1, 2, 3]);
+    assert_eq!(
+        parse::<Bounded>(BOUNDED, ";").unwrap_err(),
+        vec!["Expected an integer literal"]
+    );
+    assert_eq!(
+        parse::<Bounded>(BOUNDED, "1 2 3 4 ;").unwrap_err(),
+        vec!["Expected at most 3 repetitions"]
+    );
+}
+
+#[test]
+fn surplus_span() {
+    // the error points at the first repetition over the maximum
+    let input = "1 2 3 4 ;";
+    let patterns = Pattern::<DynamicOutput>::compile(BOUNDED).unwrap();
+    assert_eq!(column(Bounded::parse(&mut stream(input)).unwrap_err()), 6);
+    assert_eq!(
+        column(Pattern::execute(&patterns, &mut stream(input)).unwrap_err()),
+        6
+    );
+}
+
+#[test]
+fn greedy() {
+    // a non-greedy repetition stops as soon as what follows it matches, a greedy one only stops
+    // at its maximum or when its pattern does not match
+    let (generated, runtime) = parse::<Lazy>(LAZY, "a b ;").unwrap();
+    assert_eq!(generated.items, vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("items")), vec!["a", "b"]);
+    assert_eq!(
+        parse::<Lazy>(LAZY, "a ; b ;").unwrap_err(),
+        vec!["Input is too long"]
+    );
+    assert_eq!(
+        parse::<Greedy>(GREEDY, "a b ;").unwrap_err(),
+        vec!["Expected `;`"]
+    );
+    assert_eq!(
+        parse::<Greedy>(GREEDY, "a ; b ;").unwrap_err(),
+        vec!["Expected at most 3 repetitions"]
+    );
+}
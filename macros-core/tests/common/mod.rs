@@ -0,0 +1,57 @@
+//! Helpers shared by the tests, which check that the parsers generated by `parser!` and the same
+//! patterns compiled at runtime with `Pattern::compile` agree on what they match and on the errors
+//! they report.
+#![allow(dead_code)]
+
+use std::{fmt::Debug, str::FromStr};
+
+use macros_core::{DynamicOutput, MacroStream, MacrosError, Match, Parse, Pattern};
+use proc_macro2::TokenStream;
+
+pub fn stream(input: &str) -> MacroStream {
+    TokenStream::from_str(input).unwrap().into()
+}
+
+pub fn text(m: &Match) -> String {
+    MacroStream::from(m.clone()).to_string()
+}
+
+pub fn texts(matches: &[Match]) -> Vec<String> {
+    matches.iter().map(text).collect()
+}
+
+pub fn errors(error: MacrosError) -> Vec<String> {
+    match error {
+        MacrosError::Multiple(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        error => vec![error.to_string()],
+    }
+}
+
+/// Parse the input with both the generated parser and the compiled pattern, checking that either
+/// both match or both fail with the same errors.
+pub fn parse<T: Parse + Debug>(
+    pattern: &str,
+    input: &str,
+) -> Result<(T, DynamicOutput), Vec<String>> {
+    let patterns = Pattern::<DynamicOutput>::compile(pattern).unwrap();
+    match (
+        T::parse(&mut stream(input)),
+        Pattern::execute(&patterns, &mut stream(input)),
+    ) {
+        (Ok(generated), Ok(runtime)) => Ok((generated, runtime)),
+        (Err(generated), Err(runtime)) => {
+            let generated = errors(generated);
+            assert_eq!(
+                generated,
+                errors(runtime),
+                "different errors for {:?}",
+                input
+            );
+            Err(generated)
+        },
+        (generated, runtime) => panic!(
+            "only one of the parsers matched {:?}: {:?} {:?}",
+            input, generated, runtime
+        ),
+    }
+}
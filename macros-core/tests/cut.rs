@@ -0,0 +1,33 @@
+//! Checks cuts (`{}!`).
+
+mod common;
+
+use common::{parse, text};
+use macros_core::parser;
+
+const CUT: &str = "{ struct {}! { {}ident : name : String }@ ; | { {}$ : other }@ {}$ {}$ }&";
+const NO_CUT: &str = "{ struct { {}ident : name : String }@ ; | { {}$ : other }@ {}$ {}$ }&";
+
+parser! {
+    rules {
+        Cut => { struct {}! { {}ident : name : String }@ ; | { {}$ : other }@ {}$ {}$ }& ;
+        NoCut => { struct { {}ident : name : String }@ ; | { {}$ : other }@ {}$ {}$ }&
+    }
+}
+
+#[test]
+fn cut() {
+    let (generated, runtime) = parse::<Cut>(CUT, "struct A ;").unwrap();
+    assert_eq!(generated.name.as_deref(), Some("A"));
+    assert_eq!(text(runtime.get("name").unwrap()), "A");
+    let (generated, runtime) = parse::<Cut>(CUT, "x y z").unwrap();
+    assert!(generated.other.is_some());
+    assert_eq!(text(runtime.get("other").unwrap()), "x");
+    assert_eq!(
+        parse::<Cut>(CUT, "struct 1 ;").unwrap_err(),
+        vec!["Expected an identifier"]
+    );
+    let (generated, runtime) = parse::<NoCut>(NO_CUT, "struct 1 ;").unwrap();
+    assert!(generated.name.is_none());
+    assert_eq!(text(runtime.get("other").unwrap()), "struct");
+}
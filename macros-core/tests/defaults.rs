@@ -0,0 +1,49 @@
+//! Checks default values of parameters (like `{... : name : Type = default}@`).
+
+mod common;
+
+use std::borrow::Cow;
+
+use common::stream;
+use macros_core::{parser, Match, Parse};
+
+/// Check that the size is already set to its default when the name is validated.
+fn check<'a>(output: Cow<'a, Buffer>, _: &Match) -> (Result<(), String>, Cow<'a, Buffer>) {
+    match output.size {
+        4 => (Ok(()), output),
+        size => (
+            Err(format!("the size was {} instead of the default", size)),
+            output,
+        ),
+    }
+}
+
+parser! {
+    Buffer => { {}ident : name : String }@ {check}= { = { {}int : size : u8 = 4 }@ }?
+}
+
+parser! {
+    Either => { { {}int : number : u8 = 1 }@ | x }&
+}
+
+#[derive(Parse, Debug, Default)]
+#[pattern({ {}ident : name }@ { : { {}$ : kind = "any".to_string() }@ }?)]
+struct Typed {
+    name: String,
+    kind: String,
+}
+
+#[test]
+fn defaults() {
+    let buffer = Buffer::parse(&mut stream("a")).unwrap();
+    assert_eq!(buffer.size, 4);
+    // the default is replaced once the parameter matches
+    let buffer = Buffer::parse(&mut stream("a = 8")).unwrap();
+    assert_eq!(buffer.size, 8);
+    assert_eq!(Either::parse(&mut stream("x")).unwrap().number, 1);
+    assert_eq!(Either::parse(&mut stream("3")).unwrap().number, 3);
+    let typed = Typed::parse(&mut stream("a")).unwrap();
+    assert_eq!(typed.kind, "any");
+    let typed = Typed::parse(&mut stream("a : int")).unwrap();
+    assert_eq!(typed.kind, "int");
+}
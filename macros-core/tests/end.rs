@@ -0,0 +1,64 @@
+//! Checks that trailing input is rejected, along with the end of input (`{}^`) and rest of input
+//! (`{}rest`) patterns.
+
+mod common;
+
+use common::{parse, stream, text};
+use macros_core::{parser, MacroStream, Parse};
+
+const PREFIX: &str = "a";
+
+parser! {
+    Prefix => a
+}
+
+const END: &str = "[ { {}ident : name : String }@ {}^ ] { {}int : number : u8 }@";
+
+parser! {
+    End => [ { {}ident : name : String }@ {}^ ] { {}int : number : u8 }@
+}
+
+const REST: &str = "a { {}rest : rest }@";
+
+parser! {
+    Rest => a { {}rest : rest }@
+}
+
+#[test]
+fn trailing() {
+    assert!(parse::<Prefix>(PREFIX, "a").is_ok());
+    assert_eq!(
+        parse::<Prefix>(PREFIX, "a b c").unwrap_err(),
+        vec!["Input is too long"]
+    );
+    // parsing only a prefix leaves the rest of the input in the stream
+    let mut input = stream("a b c");
+    Prefix::parse_prefix(&mut input).unwrap();
+    assert_eq!(input, stream("b c"));
+}
+
+#[test]
+fn end() {
+    let (generated, runtime) = parse::<End>(END, "[a] 1").unwrap();
+    assert_eq!(generated.name, "a");
+    assert_eq!(generated.number, 1);
+    assert_eq!(text(runtime.get("name").unwrap()), "a");
+    // the end of the group is required before the group has been fully matched
+    assert_eq!(
+        parse::<End>(END, "[a b] 1").unwrap_err(),
+        vec!["Expected the end of input"]
+    );
+}
+
+#[test]
+fn rest() {
+    let (generated, runtime) = parse::<Rest>(REST, "a b c").unwrap();
+    assert_eq!(MacroStream::from(generated.rest), stream("b c"));
+    assert_eq!(
+        MacroStream::from(runtime.get("rest").unwrap().clone()),
+        stream("b c")
+    );
+    let (generated, runtime) = parse::<Rest>(REST, "a").unwrap();
+    assert!(MacroStream::from(generated.rest).is_empty());
+    assert!(runtime.contains("rest"));
+}
@@ -0,0 +1,59 @@
+//! Checks positive (`{...}>`) and negative (`{...}!`) lookaheads.
+
+mod common;
+
+use common::{parse, text, texts};
+use macros_core::parser;
+
+const POSITIVE: &str = "{ {}ident : name : String }@ { ( { {}$ : args }@ ) }> {}group";
+
+parser! {
+    Positive => { {}ident : name : String }@ { ( { {}$ : args }@ ) }> {}group
+}
+
+const NEGATIVE: &str = "{ fn }! { {}ident : name : String }@";
+
+parser! {
+    Negative => { fn }! { {}ident : name : String }@
+}
+
+const UNTIL: &str = "{ { { ; }! {}$ : body }@ }* ;";
+
+parser! {
+    Until => { { { ; }! {}$ : body }@ }* ;
+}
+
+#[test]
+fn positive() {
+    // the lookahead does not consume the group or set its parameters
+    let (generated, runtime) = parse::<Positive>(POSITIVE, "f (a)").unwrap();
+    assert_eq!(generated.name, "f");
+    assert_eq!(text(runtime.get("name").unwrap()), "f");
+    assert!(!runtime.contains("args"));
+    assert_eq!(
+        parse::<Positive>(POSITIVE, "f [a]").unwrap_err(),
+        vec!["Expected `(`"]
+    );
+    assert_eq!(
+        parse::<Positive>(POSITIVE, "f (a b)").unwrap_err(),
+        vec!["Expected `)`"]
+    );
+}
+
+#[test]
+fn negative() {
+    let (generated, runtime) = parse::<Negative>(NEGATIVE, "f").unwrap();
+    assert_eq!(generated.name, "f");
+    assert_eq!(text(runtime.get("name").unwrap()), "f");
+    assert_eq!(
+        parse::<Negative>(NEGATIVE, "fn").unwrap_err(),
+        vec!["Unexpected `fn`"]
+    );
+    let (generated, runtime) = parse::<Until>(UNTIL, "a b ;").unwrap();
+    assert_eq!(texts(&generated.body), vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("body")), vec!["a", "b"]);
+    assert_eq!(
+        parse::<Until>(UNTIL, "a b").unwrap_err(),
+        vec!["Expected one of any token, `;`"]
+    );
+}
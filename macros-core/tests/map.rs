@@ -0,0 +1,40 @@
+//! Checks mapping functions on parameters (like `{... : name : Type => map}@`).
+
+mod common;
+
+use common::stream;
+use macros_core::{parser, MacroStream, MacrosError, Match, Parse, ParseError, Span};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Level {
+    #[default]
+    Low,
+    High,
+}
+
+#[allow(clippy::result_large_err)]
+fn level(m: Match, span: Span) -> Result<Level, MacrosError> {
+    match MacroStream::from(m).to_string().as_str() {
+        "low" => Ok(Level::Low),
+        "high" => Ok(Level::High),
+        level => Err(ParseError::new(span, format!("unknown level {}", level).into()).into()),
+    }
+}
+
+parser! {
+    Log => { {}ident : level : Level => level }@ { { {}$ : length : usize => |m: Match, _| Ok(MacroStream::from(m).len()) }@ }?
+}
+
+#[test]
+fn map() {
+    let log = Log::parse(&mut stream("high")).unwrap();
+    assert_eq!(log.level, Level::High);
+    assert_eq!(log.length, None);
+    let log = Log::parse(&mut stream("low [a b c]")).unwrap();
+    assert_eq!(log.level, Level::Low);
+    assert_eq!(log.length, Some(1));
+    assert_eq!(
+        Log::parse(&mut stream("medium")).unwrap_err().to_string(),
+        "unknown level medium"
+    );
+}
@@ -0,0 +1,108 @@
+//! Checks that memoized parsers (`#[memoize]`) give the same results as parsers that are not.
+
+mod common;
+
+use std::{
+    borrow::Cow,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use common::stream;
+use macros_core::{parser, Match, Parse};
+
+static PLAIN_CALLS: AtomicUsize = AtomicUsize::new(0);
+static MEMOIZED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Count how many times the patterns of `Plain` are matched.
+fn plain<'a>(output: Cow<'a, Plain>, _: &Match) -> (Result<(), String>, Cow<'a, Plain>) {
+    PLAIN_CALLS.fetch_add(1, Ordering::Relaxed);
+    (Ok(()), output)
+}
+
+/// Count how many times the patterns of `Memoized` are matched.
+fn memoized<'a>(output: Cow<'a, Memoized>, _: &Match) -> (Result<(), String>, Cow<'a, Memoized>) {
+    MEMOIZED_CALLS.fetch_add(1, Ordering::Relaxed);
+    (Ok(()), output)
+}
+
+parser! {
+    Plain => { {}ident : name : String }@ {plain}= { ( { {}int : args : u8 }@ ) }?
+}
+
+parser! {
+    #[memoize]
+    Memoized => { {}ident : name : String }@ {memoized}= { ( { {}int : args : u8 }@ ) }?
+}
+
+// both alternatives start with the same item, so it is parsed twice at the same position unless
+// it is memoized
+parser! {
+    PlainChoice => { { { Plain }# : statement : Plain }@ ; | { { Plain }# : expression : Plain }@ }&
+}
+
+parser! {
+    MemoizedChoice => { { { Memoized }# : statement : Memoized }@ ; | { { Memoized }# : expression : Memoized }@ }&
+}
+
+/// Summarize the result of a parse so that the results of both parsers can be compared.
+fn summary(
+    statement: Option<(String, Option<u8>)>,
+    expression: Option<(String, Option<u8>)>,
+) -> String {
+    format!("{:?} {:?}", statement, expression)
+}
+
+fn plain_choice(input: &str) -> Result<String, String> {
+    PlainChoice::parse(&mut stream(input))
+        .map(|c| {
+            summary(
+                c.statement.map(|s| (s.name, s.args)),
+                c.expression.map(|e| (e.name, e.args)),
+            )
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn memoized_choice(input: &str) -> Result<String, String> {
+    MemoizedChoice::parse(&mut stream(input))
+        .map(|c| {
+            summary(
+                c.statement.map(|s| (s.name, s.args)),
+                c.expression.map(|e| (e.name, e.args)),
+            )
+        })
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn memoize() {
+    for input in ["f ;", "f(1) ;", "f", "f(1)", "f(1) ,", "f(x)", "1"] {
+        assert_eq!(plain_choice(input), memoized_choice(input), "{:?}", input);
+    }
+    assert_eq!(
+        memoized_choice("f(1) ;").unwrap(),
+        r#"Some(("f", Some(1))) None"#
+    );
+    // the item is only parsed once when it is memoized
+    PLAIN_CALLS.store(0, Ordering::Relaxed);
+    MEMOIZED_CALLS.store(0, Ordering::Relaxed);
+    plain_choice("f(1)").unwrap();
+    memoized_choice("f(1)").unwrap();
+    assert_eq!(PLAIN_CALLS.load(Ordering::Relaxed), 2);
+    assert_eq!(MEMOIZED_CALLS.load(Ordering::Relaxed), 1);
+}
+
+// memoizing a derived parser checks that the struct is `Clone + 'static`, generics included
+#[derive(Parse, Default, Clone)]
+#[memoize]
+#[pattern({ {}ident : name }@)]
+struct Generic<T: Clone + Default + 'static> {
+    name: String,
+    marker: PhantomData<T>,
+}
+
+#[test]
+fn generic() {
+    assert_eq!(Generic::<u8>::parse(&mut stream("f")).unwrap().name, "f");
+}
@@ -0,0 +1,60 @@
+//! Checks named choices, whose parameter is a generated enum.
+
+mod common;
+
+use common::{parse, text};
+use macros_core::{parser, MatchTree};
+
+/// Get the index of the alternative that matched, from the tree of a parameter that is a choice.
+fn variant(tree: &MatchTree) -> usize {
+    match tree {
+        MatchTree::Sequence(trees) => match trees.as_slice() {
+            [MatchTree::Choice(i, _)] => *i,
+            _ => panic!("expected a choice, found {:?}", trees),
+        },
+        _ => panic!("expected a choice, found {:?}", tree),
+    }
+}
+
+const NAMED_CHOICE: &str =
+    "{ { Unit: unit | Pair: ( { {}int : a : u8 }@ , { {}int : b : u8 }@ ) }& : kind : enum Kind }@";
+
+parser! {
+    Named => { { Unit: unit | Pair: ( { {}int : a : u8 }@ , { {}int : b : u8 }@ ) }& : kind : enum Kind }@
+}
+
+#[test]
+fn named_choice() {
+    let (generated, runtime) = parse::<Named>(NAMED_CHOICE, "(1, 2)").unwrap();
+    assert!(matches!(generated.kind, Kind::Pair { a: 1, b: 2 }));
+    assert_eq!(variant(runtime.get_tree("kind").unwrap()), 1);
+    assert_eq!(text(runtime.get("a").unwrap()), "1");
+    assert_eq!(text(runtime.get("b").unwrap()), "2");
+    let (generated, runtime) = parse::<Named>(NAMED_CHOICE, "unit").unwrap();
+    assert!(matches!(generated.kind, Kind::Unit));
+    assert_eq!(variant(runtime.get_tree("kind").unwrap()), 0);
+    assert!(!runtime.contains("a"));
+    assert_eq!(
+        parse::<Named>(NAMED_CHOICE, "(1 2)").unwrap_err(),
+        vec!["Expected `,`"]
+    );
+}
+
+const PEEKED: &str =
+    "{ { { Unit: unit | Pair: pair }& : kind : enum PeekedKind }@ }> { {}$ : first }@";
+
+parser! {
+    Peeked => { { { Unit: unit | Pair: pair }& : kind : enum PeekedKind }@ }> { {}$ : first }@
+}
+
+#[test]
+fn lookahead() {
+    // the parameter of the choice is not set inside of a lookahead, so it is not a field
+    let (generated, runtime) = parse::<Peeked>(PEEKED, "pair").unwrap();
+    assert_eq!(text(&generated.first), "pair");
+    assert!(!runtime.contains("kind"));
+    assert_eq!(
+        parse::<Peeked>(PEEKED, "both").unwrap_err(),
+        vec!["Expected one of `unit`, `pair`"]
+    );
+}
@@ -0,0 +1,38 @@
+//! Checks repetitions whose body is parsed into a nested struct (like `{ {...}* : name : struct
+//! Type }@`).
+
+mod common;
+
+use common::{parse, texts};
+use macros_core::parser;
+
+const NESTED: &str = "{ { { {}ident : key : String }@ { = { {}int : value : u8 }@ }? },* : entries : struct Entry }@";
+
+parser! {
+    Entries => { { { {}ident : key : String }@ { = { {}int : value : u8 }@ }? },* : entries : struct Entry }@
+}
+
+#[test]
+fn nested() {
+    // the parameters of each repetition stay together, even the ones that did not match
+    let (generated, runtime) = parse::<Entries>(NESTED, "a = 1, b, c = 3").unwrap();
+    let entries = generated
+        .entries
+        .iter()
+        .map(|e| (e.key.as_str(), e.value))
+        .collect::<Vec<_>>();
+    assert_eq!(entries, vec![("a", Some(1)), ("b", None), ("c", Some(3))]);
+    // patterns compiled at runtime set the parameters of the body directly
+    assert_eq!(texts(runtime.get_all("key")), vec!["a", "b", "c"]);
+    assert_eq!(texts(runtime.get_all("value")), vec!["1", "3"]);
+    let (generated, _) = parse::<Entries>(NESTED, "").unwrap();
+    assert!(generated.entries.is_empty());
+    assert_eq!(
+        parse::<Entries>(NESTED, "a = x").unwrap_err(),
+        vec!["Expected an integer literal"]
+    );
+    assert_eq!(
+        parse::<Entries>(NESTED, "a b").unwrap_err(),
+        vec!["Expected one of `=`, `,`"]
+    );
+}
@@ -0,0 +1,75 @@
+//! Checks the patterns that the generated parsers and the compiled patterns have in common.
+
+mod common;
+
+use common::{parse, text, texts};
+use macros_core::parser;
+
+const CHOICE: &str =
+    "{ struct { {}ident : strukt : String }@ | enum { {}ident : enm : String }@ }& ;";
+
+parser! {
+    Choice => { struct { {}ident : strukt : String }@ | enum { {}ident : enm : String }@ }& ;
+}
+
+#[test]
+fn choice() {
+    let (generated, runtime) = parse::<Choice>(CHOICE, "struct A ;").unwrap();
+    assert_eq!(generated.strukt.as_deref(), Some("A"));
+    assert_eq!(generated.enm, None);
+    assert_eq!(text(runtime.get("strukt").unwrap()), "A");
+    assert!(!runtime.contains("enm"));
+    let (generated, runtime) = parse::<Choice>(CHOICE, "enum B ;").unwrap();
+    assert_eq!(generated.enm.as_deref(), Some("B"));
+    assert_eq!(text(runtime.get("enm").unwrap()), "B");
+    assert_eq!(
+        parse::<Choice>(CHOICE, "union C ;").unwrap_err(),
+        vec!["Expected one of `struct`, `enum`"]
+    );
+    assert_eq!(
+        parse::<Choice>(CHOICE, "struct 1 ;").unwrap_err(),
+        vec!["Expected an identifier"]
+    );
+}
+
+const REPETITION: &str = "{ { {}ident : names : String }@ }* { { {}int : numbers : u8 }@ }+ ;";
+
+parser! {
+    Repetition => { { {}ident : names : String }@ }* { { {}int : numbers : u8 }@ }+ ;
+}
+
+const LAZY: &str = "{ { {}$ : items : String }@ }* ;";
+
+parser! {
+    Lazy => { { {}$ : items : String }@ }* ;
+}
+
+const GREEDY: &str = "{ { {}$ : items }@ }** ;";
+
+parser! {
+    Greedy => { { {}$ : items }@ }** ;
+}
+
+#[test]
+fn repetition() {
+    let (generated, runtime) = parse::<Repetition>(REPETITION, "a b 1 2 ;").unwrap();
+    assert_eq!(generated.names, vec!["a", "b"]);
+    assert_eq!(generated.numbers, vec![1, 2]);
+    assert_eq!(texts(runtime.get_all("names")), vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("numbers")), vec!["1", "2"]);
+    let (generated, runtime) = parse::<Repetition>(REPETITION, "1 ;").unwrap();
+    assert!(generated.names.is_empty());
+    assert!(!runtime.contains("names"));
+    assert_eq!(
+        parse::<Repetition>(REPETITION, "a b ;").unwrap_err(),
+        vec!["Expected one of an identifier, an integer literal"]
+    );
+    // a non-greedy repetition stops as soon as what follows it matches, a greedy one does not
+    let (generated, runtime) = parse::<Lazy>(LAZY, "a b ;").unwrap();
+    assert_eq!(generated.items, vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("items")), vec!["a", "b"]);
+    assert_eq!(
+        parse::<Greedy>(GREEDY, "a b ;").unwrap_err(),
+        vec!["Expected one of any token, `;`"]
+    );
+}
@@ -0,0 +1,97 @@
+//! Checks precedence tables for expressions with operators (see `Precedence`).
+
+mod common;
+
+use std::fmt::{self, Display, Formatter};
+
+use common::stream;
+use macros_core::{parser, Parse};
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Fact(Box<Expr>),
+}
+
+impl Default for Expr {
+    fn default() -> Self {
+        Expr::Num(0)
+    }
+}
+
+/// Write the expression with every operation in parentheses to show how it was grouped.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Binary(op, a, b) => write!(f, "({} {} {})", a, op, b),
+            Expr::Neg(a) => write!(f, "(-{})", a),
+            Expr::Fact(a) => write!(f, "({}!)", a),
+        }
+    }
+}
+
+fn binary(op: &'static str) -> impl Fn(Expr, Expr) -> Expr {
+    move |a, b| Expr::Binary(op, a.into(), b.into())
+}
+
+parser! {
+    rules {
+        Atom => { { Num: { {}int : num : i64 }@ | Paren: ( { { Expr }# : expr : Box<Expr> }@ ) }& : atom : enum AtomKind }@ ;
+        Expr => precedence {
+            atom Atom => |a| match a.atom {
+                AtomKind::Num { num } => Expr::Num(num),
+                AtomKind::Paren { expr } => *expr,
+            };
+            prefix "-" 5 => |a| Expr::Neg(a.into());
+            postfix "!" 7 => |a| Expr::Fact(a.into());
+            infix "+" 3 left => binary("+");
+            infix "-" 3 left => binary("-");
+            infix "*" 4 left => binary("*");
+            infix "**" 6 right => binary("**");
+            infix "==" 2 none => binary("==");
+            infix and 1 left => binary("and");
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<String, String> {
+    Expr::parse(&mut stream(input))
+        .map(|expr| expr.to_string())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn precedence() {
+    assert_eq!(parse("1 + 2 * 3 - 4").unwrap(), "((1 + (2 * 3)) - 4)");
+    assert_eq!(parse("(1 + 2) * 3").unwrap(), "((1 + 2) * 3)");
+    assert_eq!(parse("-3! + 2 * -1").unwrap(), "((-(3!)) + (2 * (-1)))");
+    assert_eq!(parse("-2 ** 2").unwrap(), "(-(2 ** 2))");
+    assert_eq!(
+        parse("1 == 2 and 3 == 1 + 2").unwrap(),
+        "((1 == 2) and (3 == (1 + 2)))"
+    );
+}
+
+#[test]
+fn associativity() {
+    assert_eq!(parse("1 - 2 - 3").unwrap(), "((1 - 2) - 3)");
+    assert_eq!(parse("2 ** 3 ** 2").unwrap(), "(2 ** (3 ** 2))");
+    assert_eq!(parse("(1 == 2) == 3").unwrap(), "((1 == 2) == 3)");
+    assert_eq!(
+        parse("1 == 2 == 3").unwrap_err(),
+        "The operator `==` is not associative, use parentheses to group it"
+    );
+}
+
+#[test]
+fn errors() {
+    assert_eq!(
+        parse("1 +").unwrap_err(),
+        "Expected one of an integer literal, `(`"
+    );
+    assert_eq!(parse("1 2").unwrap_err(), "Input is too long");
+    assert_eq!(parse("(1 + 2 3)").unwrap_err(), "Expected `)`");
+}
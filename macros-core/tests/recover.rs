@@ -0,0 +1,28 @@
+//! Checks recovering from errors (like `{... | sync}%`).
+
+mod common;
+
+use common::{parse, texts};
+use macros_core::parser;
+
+const RECOVER: &str = "{ { let { {}ident : names : String }@ = {}int | ; }% ; }*";
+
+parser! {
+    Recover => { { let { {}ident : names : String }@ = {}int | ; }% ; }*
+}
+
+#[test]
+fn recover() {
+    let (generated, runtime) = parse::<Recover>(RECOVER, "let a = 1 ; let b = 2 ;").unwrap();
+    assert_eq!(generated.names, vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("names")), vec!["a", "b"]);
+    assert_eq!(
+        parse::<Recover>(RECOVER, "let a 1 ; let b = 2 ; let = 3 ;").unwrap_err(),
+        vec!["Expected `=`", "Expected an identifier"]
+    );
+    // recovering from a statement that does not start with `let` would skip the rest of the input
+    assert_eq!(
+        parse::<Recover>(RECOVER, "let a 1 ; lat b = 2 ;").unwrap_err(),
+        vec!["Expected `=`", "Expected `let`"]
+    );
+}
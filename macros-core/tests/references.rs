@@ -0,0 +1,77 @@
+//! Checks references to other parsers (like `{ Type }#`).
+
+mod common;
+
+use common::stream;
+use macros_core::{parser, DynamicOutput, Parse, Pattern};
+
+parser! {
+    rules {
+        Entry => { {}ident : key : String }@ = { {}int : value : u8 }@ ;
+        Entries => { { { Entry }# : entries : Entry }@ },*
+    }
+}
+
+parser! {
+    Arrow => a ; B => { {}ident : target : String }@
+}
+
+#[test]
+fn references() {
+    let entries = Entries::parse(&mut stream("a = 1, b = 2")).unwrap();
+    let keys = entries
+        .entries
+        .iter()
+        .map(|e| e.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec!["a", "b"]);
+    assert_eq!(entries.entries[1].value, 2);
+    assert_eq!(
+        Entries::parse(&mut stream("a = 1, b 2"))
+            .unwrap_err()
+            .to_string(),
+        "Expected `=`"
+    );
+    // the referenced parser only exists in generated code
+    let Err(error) = Pattern::<DynamicOutput>::compile("{ { { Entry }# : entries : Entry }@ },*")
+    else {
+        panic!("a reference was compiled");
+    };
+    assert_eq!(
+        error.to_string(),
+        "Found a reference to Entry, but references to other parsers (like { Type }#) are only \
+         supported by `parser!` and `#[derive(Parse)]`"
+    );
+}
+
+parser! {
+    Peeked => { { { Entry }# : entry : Entry }@ }> {}rest
+}
+
+parser! {
+    NotPeeked => { { { Entry }# : entry : Entry }@ }! {}rest
+}
+
+#[test]
+fn lookahead() {
+    // the parameters inside of a lookahead are not set, so they are not fields of the output
+    assert!(Peeked::parse(&mut stream("a = 1")).is_ok());
+    assert_eq!(
+        Peeked::parse(&mut stream("a 1")).unwrap_err().to_string(),
+        "Expected `=`"
+    );
+    assert!(NotPeeked::parse(&mut stream("a 1")).is_ok());
+    assert_eq!(
+        NotPeeked::parse(&mut stream("a = 1"))
+            .unwrap_err()
+            .to_string(),
+        "Unexpected `a`"
+    );
+}
+
+#[test]
+fn rules() {
+    // outside of a `rules` block, a `;` followed by `Name =>` is part of the single rule
+    let arrow = Arrow::parse(&mut stream("a ; B => c")).unwrap();
+    assert_eq!(arrow.target, "c");
+}
@@ -0,0 +1,50 @@
+//! Checks separated repetitions (like `{...},*`).
+
+mod common;
+
+use common::{parse, texts};
+use macros_core::{parser, Parse};
+
+const SEPARATED: &str = "[ { { {}int : items : u8 }@ },* ] { { {}ident : names : String }@ },+!";
+
+parser! {
+    Separated => [ { { {}int : items : u8 }@ },* ] { { {}ident : names : String }@ },+!
+}
+
+#[derive(Parse, Debug, Default)]
+#[pattern([ { { {}int : items }@ },* ] { { {}ident : names }@ },+!)]
+struct DerivedSeparated {
+    items: Vec<u8>,
+    names: Vec<String>,
+}
+
+#[test]
+fn separated() {
+    let (generated, runtime) = parse::<Separated>(SEPARATED, "[1, 2,] a, b").unwrap();
+    assert_eq!(generated.items, vec![1, 2]);
+    assert_eq!(generated.names, vec!["a", "b"]);
+    assert_eq!(texts(runtime.get_all("items")), vec!["1", "2"]);
+    assert_eq!(texts(runtime.get_all("names")), vec!["a", "b"]);
+    let (generated, runtime) = parse::<Separated>(SEPARATED, "[] a").unwrap();
+    assert!(generated.items.is_empty());
+    assert!(!runtime.contains("items"));
+    let (derived, _) = parse::<DerivedSeparated>(SEPARATED, "[1,] a, b").unwrap();
+    assert_eq!(derived.items, vec![1]);
+    assert_eq!(derived.names, vec!["a", "b"]);
+    assert_eq!(
+        parse::<DerivedSeparated>(SEPARATED, "[1] a,").unwrap_err(),
+        vec!["Expected an identifier"]
+    );
+    assert_eq!(
+        parse::<Separated>(SEPARATED, "[1] a,").unwrap_err(),
+        vec!["Expected an identifier"]
+    );
+    assert_eq!(
+        parse::<Separated>(SEPARATED, "[1 2] a").unwrap_err(),
+        vec!["Expected one of `,`, `]`"]
+    );
+    assert_eq!(
+        parse::<Separated>(SEPARATED, "[1]").unwrap_err(),
+        vec!["Expected an identifier"]
+    );
+}
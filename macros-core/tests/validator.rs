@@ -0,0 +1,86 @@
+//! Checks validators (`{...}=`), including boxed closures and the spans of their errors.
+
+mod common;
+
+use common::stream;
+use macros_core::{
+    parser, validator, DynamicOutput, MacroStream, MacrosError, Match, Parse, ParseError, Pattern,
+    Validator,
+};
+
+/// Build a validator from configuration, which limits the number of tokens of a match.
+fn at_most<T: Clone + 'static>(max: usize) -> Validator<T> {
+    validator(
+        move |output, m: &Match| match MacroStream::from(m.clone()).len() {
+            len if len > max => (Err(format!("expected at most {} tokens", max)), output),
+            _ => (Ok(()), output),
+        },
+    )
+}
+
+/// Reject a match that is not a single token, with the error at the second token.
+fn single<T: Clone + 'static>() -> Validator<T> {
+    validator(
+        |output, m: &Match| match MacroStream::from(m.clone()).tokens() {
+            [_, second, ..] => (
+                Err(ParseError::new(
+                    second.span(),
+                    "expected a single token".into(),
+                )),
+                output,
+            ),
+            _ => (Ok(()), output),
+        },
+    )
+}
+
+parser! {
+    Call => { {}ident : name : String }@ ( { { {}$ : args }@ }* {at_most(2)}= ) { {}rest : rest }@ {single()}=
+}
+
+/// Get the start and end columns of the span of an error.
+fn columns(error: MacrosError) -> (usize, usize) {
+    match error {
+        MacrosError::Parse(error) => (error.span.start().column, error.span.end().column),
+        error => panic!("expected a parse error, found {:?}", error),
+    }
+}
+
+#[test]
+fn boxed() {
+    let call = Call::parse(&mut stream("f(a b)")).unwrap();
+    assert_eq!(call.name, "f");
+    assert_eq!(call.args.len(), 2);
+    let error = Call::parse(&mut stream("f(a b c)")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Validator failed with message: expected at most 2 tokens"
+    );
+    // the error is reported at the whole match by default
+    assert_eq!(columns(error), (2, 7));
+    assert!(Call::parse(&mut stream("f(a) x")).is_ok());
+    // an error with its own span keeps it
+    let error = Call::parse(&mut stream("f(a) x y")).unwrap_err();
+    assert_eq!(error.to_string(), "expected a single token");
+    assert_eq!(columns(error), (7, 8));
+}
+
+#[test]
+fn runtime() {
+    let patterns = Pattern::<DynamicOutput>::any()
+        .capture("items")
+        .zero_or_more()
+        .validate(|output, m: &Match| match m {
+            Match::Many(items) if items.len() > 2 => (Err("too many items".to_string()), output),
+            _ => (Ok(()), output),
+        })
+        .build();
+    let output = Pattern::execute(&patterns, &mut stream("a b")).unwrap();
+    assert_eq!(output.get_all("items").len(), 2);
+    let error = Pattern::execute(&patterns, &mut stream("a b c")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Validator failed with message: too many items"
+    );
+    assert_eq!(columns(error), (0, 5));
+}
@@ -0,0 +1,652 @@
+use macros_utils::{
+    call_site, LiteralKind, MacroStream, MacrosError, ParseError, ParserOutput, Pattern, Repr,
+    Token, TokenClass, Trailing,
+};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+
+/// Lowers a tree of `Pattern`s into specialized matching functions.
+///
+/// Every pattern node becomes its own associated function on the output type with the signature
+/// `fn(&mut self, &mut macros_core::MacroStream) -> Result<macros_core::MatchTree, macros_core::MacrosError>`,
+/// so the generated parser never has to interpret a `Pattern` at runtime.
+#[derive(Default)]
+pub struct Codegen {
+    functions: Vec<TokenStream>,
+    /// Whether parameters and validators are currently ignored, which is the case while lowering
+    /// the alternatives of a `Pattern::NamedChoice` that is only matched for its tokens (when its
+    /// parameter has a mapping function), since their parameters belong to the variants of the
+    /// generated enum rather than to the output type.
+    discard: bool,
+    /// Whether parameters are currently matched only for their tokens, which is the case while
+    /// lowering the patterns of a `Pattern::Lookahead`, since they are matched with a throwaway
+    /// output and the output type has no fields for them (see `Pattern::params`).
+    lookahead: bool,
+    /// The code to assign an already parsed `value` to each parameter that is set from a value
+    /// instead of from its match (see `is_value_param`), by the name of the parameter.
+    values: Vec<(String, TokenStream)>,
+    errors: Vec<MacrosError>,
+}
+
+impl Codegen {
+    /// Consume the generator and return the generated functions, or the errors found while
+    /// lowering the patterns.
+    pub fn finish(self) -> Result<Vec<TokenStream>, MacrosError> {
+        match self.errors.is_empty() {
+            true => Ok(self.functions),
+            false => Err(MacrosError::multiple(self.errors)),
+        }
+    }
+
+    /// Set the code to assign an already parsed `value` to each value parameter of the patterns
+    /// lowered next, by the name of the parameter.
+    pub fn values(&mut self, values: Vec<(String, TokenStream)>) {
+        self.values = values;
+    }
+
+    /// Get the code to assign an already parsed `value` to a value parameter, recording an error
+    /// at its type if the parameter has no field to assign to.
+    fn assign_value(&mut self, name: &str, type_: &MacroStream) -> TokenStream {
+        match self.values.iter().find(|(param, _)| param == name) {
+            Some((_, assign)) => assign.clone(),
+            None => {
+                self.errors.push(
+                    ParseError::new(
+                        type_.peek().map(|t| t.span()).unwrap_or_else(call_site),
+                        format!("the parameter `{}` cannot be set from its value here", name)
+                            .into(),
+                    )
+                    .into(),
+                );
+                quote!()
+            },
+        }
+    }
+
+    fn function(&mut self, body: TokenStream) -> Ident {
+        let ident = format_ident!("__macros_match_{}", self.functions.len());
+        self.functions.push(quote! {
+            #[doc(hidden)]
+            fn #ident(&mut self, stream: &mut macros_core::MacroStream) -> Result<macros_core::MatchTree, macros_core::MacrosError> {
+                #body
+            }
+        });
+        ident
+    }
+
+    /// Lower a sequence of patterns, mirroring `Pattern::match_patterns`.
+    pub fn sequence<T>(&mut self, patterns: &[Pattern<T>]) -> Ident
+    where
+        T: ToOwned<Owned = T> + ParserOutput,
+    {
+        let steps = patterns
+            .iter()
+            .enumerate()
+            .filter(|(_, pattern)| !matches!(pattern, Pattern::Validator(_, _)))
+            .map(|(i, pattern)| {
+                let ident = self.pattern(pattern, patterns.get(i + 1), patterns.get(i + 2));
+                quote! {
+                    matches.push(self.#ident(stream)?);
+                }
+            })
+            .collect::<Vec<_>>();
+        self.function(quote! {
+            let mut matches = vec![];
+            #(#steps)*
+            Ok(macros_core::MatchTree::Sequence(matches))
+        })
+    }
+
+    /// Lower a single pattern, mirroring `Pattern::match_pattern`.
+    fn pattern<T>(
+        &mut self,
+        pattern: &Pattern<T>,
+        next: Option<&Pattern<T>>,
+        next2: Option<&Pattern<T>>,
+    ) -> Ident
+    where
+        T: ToOwned<Owned = T> + ParserOutput,
+    {
+        let match_next = match next {
+            Some(Pattern::Validator(_, _)) => next2,
+            _ => next,
+        };
+        let body = match pattern {
+            Pattern::Any => quote! {
+                match stream.pop() {
+                    Some(token) => Ok(macros_core::MatchTree::Token(token)),
+                    None => Err(stream.expected("any token").into()),
+                }
+            },
+            Pattern::End => quote! {
+                match stream.peek() {
+                    Some(_) => Err(stream.expected("the end of input").into()),
+                    None => Ok(macros_core::MatchTree::None),
+                }
+            },
+            Pattern::Cut => quote! {
+                stream.cut();
+                Ok(macros_core::MatchTree::None)
+            },
+            Pattern::Rest => quote! {
+                let mut matches = vec![];
+                while let Some(token) = stream.pop() {
+                    matches.push(macros_core::MatchTree::Token(token));
+                }
+                if matches.is_empty() {
+                    Ok(macros_core::MatchTree::None)
+                } else {
+                    Ok(macros_core::MatchTree::Sequence(matches))
+                }
+            },
+            Pattern::Class(class) => {
+                let expected = class.describe();
+                let class_pattern = class_pattern(class);
+                quote! {
+                    match stream.peek() {
+                        Some(#class_pattern) => Ok(macros_core::MatchTree::Token(stream.pop().unwrap())),
+                        _ => Err(stream.expected(#expected).into()),
+                    }
+                }
+            },
+            Pattern::Choice(choices) => self.choice(choices.iter()),
+            Pattern::NamedChoice(choices) => {
+                let discard = std::mem::replace(&mut self.discard, true);
+                let body = self.choice(choices.iter().map(|(_, choice)| choice));
+                self.discard = discard;
+                body
+            },
+            Pattern::Group(delimiter, patterns) => {
+                let ident = self.sequence(patterns);
+                let start = delimiter.describe_start();
+                let end = delimiter.describe_end();
+                let delimiter = delimiter.repr("");
+                quote! {
+                    let (mut fork, span) = match stream.peek() {
+                        Some(macros_core::Token::Group {
+                            delimiter: #delimiter,
+                            stream: inner,
+                            span,
+                        }) => (stream.enter(inner), *span),
+                        _ => return Err(stream.expected(#start).into()),
+                    };
+                    let m = self.#ident(&mut fork)?;
+                    if !fork.is_empty() {
+                        return Err(fork.expected(#end).into());
+                    }
+                    stream.leave(fork);
+                    Ok(macros_core::MatchTree::Group(#delimiter, span, Box::new(m)))
+                }
+            },
+            Pattern::OneOrMore(patterns, greedy) => {
+                let ident = self.sequence(patterns);
+                let lookahead = self.lookahead(match_next.filter(|_| !greedy));
+                quote! {
+                    let mut matches = vec![];
+                    loop {
+                        let mut fork = stream.alternative();
+                        match self.#ident(&mut fork) {
+                            Ok(m) => {
+                                let progress = fork.len() < stream.len();
+                                stream.unfork(fork);
+                                matches.push(m);
+                                if !progress {
+                                    break;
+                                }
+                            },
+                            Err(e) => {
+                                if matches.is_empty() || fork.is_cut() {
+                                    return Err(e);
+                                }
+                                break;
+                            },
+                        }
+                        #lookahead
+                    }
+                    Ok(macros_core::MatchTree::Repetition(matches))
+                }
+            },
+            Pattern::ZeroOrMore(patterns, greedy) => {
+                let ident = self.sequence(patterns);
+                let lookahead = self.lookahead(match_next.filter(|_| !greedy));
+                quote! {
+                    let mut matches = vec![];
+                    loop {
+                        let mut fork = stream.alternative();
+                        match self.#ident(&mut fork) {
+                            Ok(m) => {
+                                let progress = fork.len() < stream.len();
+                                stream.unfork(fork);
+                                matches.push(m);
+                                if !progress {
+                                    break;
+                                }
+                            },
+                            Err(e) => {
+                                if fork.is_cut() {
+                                    return Err(e);
+                                }
+                                break;
+                            },
+                        }
+                        #lookahead
+                    }
+                    Ok(macros_core::MatchTree::Repetition(matches))
+                }
+            },
+            // cuts in the referenced parser do not apply to this one
+            Pattern::Reference(type_) => quote! {
+                let mut fork = stream.alternative();
+                <#type_ as macros_core::Parse>::parse_prefix(&mut fork)?;
+                let m = macros_core::MatchTree::from(stream.consumed(&fork));
+                stream.unfork(fork);
+                Ok(m)
+            },
+            // the patterns are matched with a throwaway output so that their parameters are not set
+            Pattern::Lookahead(patterns, false) => {
+                let ident = self.lookahead_sequence(patterns);
+                quote! {
+                    Self::default().#ident(&mut stream.alternative())?;
+                    Ok(macros_core::MatchTree::None)
+                }
+            },
+            Pattern::Lookahead(patterns, true) => {
+                let ident = self.lookahead_sequence(patterns);
+                quote! {
+                    match Self::default().#ident(&mut stream.lookahead()) {
+                        Ok(_) => Err(stream.unexpected().into()),
+                        Err(_) => Ok(macros_core::MatchTree::None),
+                    }
+                }
+            },
+            Pattern::Separated(patterns, separator, one, trailing) => {
+                let ident = self.sequence(patterns);
+                let expected = format!("`{}`", separator);
+                let len = separator.chars().count();
+                let item_required = match (one, trailing) {
+                    (true, Trailing::Forbid) => quote!(true),
+                    (true, _) => quote!(matches.is_empty()),
+                    (false, Trailing::Forbid) => quote!(!matches.is_empty()),
+                    (false, _) => quote!(false),
+                };
+                let separator_required = *trailing == Trailing::Require;
+                quote! {
+                    let mut matches = vec![];
+                    let mut separators = vec![];
+                    loop {
+                        let mut fork = stream.alternative();
+                        match self.#ident(&mut fork) {
+                            Ok(m) => {
+                                stream.unfork(fork);
+                                matches.push(m);
+                            },
+                            Err(e) => {
+                                if #item_required || fork.is_cut() {
+                                    return Err(e);
+                                }
+                                break;
+                            },
+                        }
+                        if !stream.starts_with_operator(#separator) {
+                            let e = stream.expected(#expected);
+                            if #separator_required {
+                                return Err(e.into());
+                            }
+                            break;
+                        }
+                        separators.push(macros_core::MatchTree::Sequence(
+                            (0..#len).map(|_| macros_core::MatchTree::Token(stream.pop().unwrap())).collect(),
+                        ));
+                    }
+                    Ok(macros_core::MatchTree::Separated(matches, separators))
+                }
+            },
+            Pattern::Repeat(patterns, min, max, greedy) => {
+                let ident = self.sequence(patterns);
+                let next_matches = match match_next.filter(|_| !greedy) {
+                    Some(next) => {
+                        let next = self.pattern(next, None, None);
+                        quote! {
+                            Self::default().#next(&mut stream.lookahead()).is_ok()
+                        }
+                    },
+                    None => quote!(false),
+                };
+                let at_max = match max {
+                    Some(max) => quote!(matches.len() == #max),
+                    None => quote!(false),
+                };
+                let (at_min, below_min) = match min {
+                    0 => (quote!(true), quote!(false)),
+                    min => (quote!(matches.len() >= #min), quote!(matches.len() < #min)),
+                };
+                let surplus = match max {
+                    Some(max) => quote! {
+                        if #at_max && !(#next_matches) {
+                            let mut fork = stream.lookahead();
+                            if Self::default().#ident(&mut fork).is_ok() && fork.len() < stream.len() {
+                                return Err(macros_core::ParseError::new(
+                                    stream.peek().map(|t| t.span()).unwrap_or_else(macros_core::call_site),
+                                    macros_core::ParseErrorKind::TooManyRepetitions(#max),
+                                ).into());
+                            }
+                        }
+                    },
+                    None => quote!(),
+                };
+                quote! {
+                    let mut matches = vec![];
+                    loop {
+                        if #at_max || #at_min && #next_matches {
+                            break;
+                        }
+                        let mut fork = stream.alternative();
+                        match self.#ident(&mut fork) {
+                            Ok(m) => {
+                                let progress = fork.len() < stream.len();
+                                stream.unfork(fork);
+                                matches.push(m);
+                                if !progress {
+                                    break;
+                                }
+                            },
+                            Err(e) => {
+                                if #below_min || fork.is_cut() {
+                                    return Err(e);
+                                }
+                                break;
+                            },
+                        }
+                    }
+                    #surplus
+                    Ok(macros_core::MatchTree::Repetition(matches))
+                }
+            },
+            Pattern::Optional(patterns) => {
+                let ident = self.sequence(patterns);
+                quote! {
+                    let mut fork = stream.alternative();
+                    match self.#ident(&mut fork) {
+                        Ok(m) => {
+                            stream.unfork(fork);
+                            Ok(macros_core::MatchTree::Optional(Some(Box::new(m))))
+                        },
+                        Err(e) if fork.is_cut() => Err(e),
+                        Err(_) => Ok(macros_core::MatchTree::Optional(None)),
+                    }
+                }
+            },
+            Pattern::Recover(patterns, sync) => {
+                let ident = self.sequence(patterns);
+                let sync = match sync.is_empty() {
+                    true => quote!(false),
+                    false => {
+                        let sync = sync.iter().map(token_pattern);
+                        quote!(#(matches!(token, #sync))||*)
+                    },
+                };
+                quote! {
+                    stream.recover(|stream| self.#ident(stream), |token| #sync)
+                }
+            },
+            Pattern::Token(token) => {
+                let expected = token.describe();
+                let token_pattern = token_pattern(token);
+                quote! {
+                    match stream.peek() {
+                        Some(token) if matches!(token, #token_pattern) => {
+                            Ok(macros_core::MatchTree::Token(stream.pop().unwrap()))
+                        },
+                        _ => Err(stream.expected(#expected).into()),
+                    }
+                }
+            },
+            Pattern::Operator(operator) => {
+                let expected = format!("`{}`", operator);
+                let len = operator.chars().count();
+                quote! {
+                    match stream.starts_with_operator(#operator) {
+                        true => Ok(macros_core::MatchTree::Sequence(
+                            (0..#len).map(|_| macros_core::MatchTree::Token(stream.pop().unwrap())).collect(),
+                        )),
+                        false => Err(stream.expected(#expected).into()),
+                    }
+                }
+            },
+            Pattern::Parameter(patterns, _, _, _, _) if self.discard || self.lookahead => {
+                let ident = self.sequence(patterns);
+                quote! {
+                    self.#ident(stream)
+                }
+            },
+            // each repetition is parsed into the nested struct by its own parser, like a reference
+            // to it that is set as a value
+            Pattern::Parameter(patterns, name, _, _, _) if pattern.nested_struct().is_some() => {
+                let (_, type_, _) = pattern.nested_struct().unwrap();
+                let body = vec![Pattern::Parameter(
+                    vec![Pattern::<T>::Reference(type_.clone())],
+                    name.clone(),
+                    type_,
+                    None,
+                    None,
+                )];
+                let repetition = match &patterns[0] {
+                    Pattern::ZeroOrMore(_, greedy) => Pattern::ZeroOrMore(body, *greedy),
+                    Pattern::OneOrMore(_, greedy) => Pattern::OneOrMore(body, *greedy),
+                    Pattern::Repeat(_, min, max, greedy) => {
+                        Pattern::Repeat(body, *min, *max, *greedy)
+                    },
+                    Pattern::Separated(_, separator, one, trailing) => {
+                        Pattern::Separated(body, separator.clone(), *one, *trailing)
+                    },
+                    _ => unreachable!("a nested struct always contains a repetition"),
+                };
+                let ident = self.sequence(&[repetition]);
+                quote! {
+                    self.#ident(stream)
+                }
+            },
+            // a mapping function is given the tokens instead of the value
+            Pattern::Parameter(patterns, name, type_, _, None)
+                if is_value_param(patterns, type_) =>
+            {
+                let assign = self.assign_value(name, type_);
+                quote! {
+                    let mut fork = stream.alternative();
+                    let value = <#type_ as macros_core::Parse>::parse_prefix(&mut fork)?;
+                    let m = macros_core::MatchTree::from(stream.consumed(&fork));
+                    stream.unfork(fork);
+                    #assign
+                    Ok(m)
+                }
+            },
+            // the variant of the generated enum is built by its own matching functions as the
+            // choice is matched (see `enum_output`), so the choice is only matched once
+            Pattern::Parameter(patterns, name, type_, _, None)
+                if matches!(patterns.as_slice(), [Pattern::NamedChoice(_)]) =>
+            {
+                let assign = self.assign_value(name, type_);
+                quote! {
+                    let (value, m) = <#type_>::__macros_choice(stream)?;
+                    #assign
+                    Ok(m)
+                }
+            },
+            Pattern::Parameter(patterns, name, _, _, _) => {
+                let ident = self.sequence(patterns);
+                quote! {
+                    let mut fork = stream.fork();
+                    let m = self.#ident(&mut fork)?;
+                    stream.unfork(fork);
+                    macros_core::ParserOutput::set_tree(self, #name, m.clone())?;
+                    Ok(m)
+                }
+            },
+            Pattern::Validator(_, _) => unreachable!(
+                "Validator pattern should not have been passed into `Codegen::pattern`"
+            ),
+        };
+        match next {
+            Some(Pattern::Validator(Some(validator), _)) if !self.discard => {
+                let ident = self.function(body);
+                self.function(quote! {
+                    let m = self.#ident(stream)?;
+                    let (res, o) = macros_core::validate(
+                        #validator,
+                        std::borrow::Cow::Borrowed(&*self),
+                        &macros_core::Match::from(m.clone()),
+                    );
+                    let (res, o) = match o {
+                        std::borrow::Cow::Owned(o) => (res, Some(o)),
+                        std::borrow::Cow::Borrowed(_) => (res, None),
+                    };
+                    if let Some(o) = o {
+                        *self = o;
+                    }
+                    res.map(|_| m)
+                })
+            },
+            _ => self.function(body),
+        }
+    }
+
+    /// Lower the patterns of a lookahead, ignoring their parameters.
+    fn lookahead_sequence<T>(&mut self, patterns: &[Pattern<T>]) -> Ident
+    where
+        T: ToOwned<Owned = T> + ParserOutput,
+    {
+        let lookahead = std::mem::replace(&mut self.lookahead, true);
+        let ident = self.sequence(patterns);
+        self.lookahead = lookahead;
+        ident
+    }
+
+    /// Lower a choice between sequences of patterns.
+    fn choice<'a, T>(&mut self, choices: impl Iterator<Item = &'a Vec<Pattern<T>>>) -> TokenStream
+    where
+        T: ToOwned<Owned = T> + ParserOutput + 'a,
+    {
+        let choices = choices
+            .enumerate()
+            .map(|(i, choice)| {
+                let ident = self.sequence(choice);
+                quote! {
+                    let mut fork = stream.choice();
+                    match self.#ident(&mut fork) {
+                        Ok(m) => {
+                            stream.unfork(fork);
+                            return Ok(macros_core::MatchTree::Choice(#i, Box::new(m)));
+                        },
+                        Err(e) if fork.is_cut() => return Err(e),
+                        Err(_) => {},
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        quote! {
+            #(#choices)*
+            Err(stream.error(macros_core::ParseErrorKind::NoMatchingChoice).into())
+        }
+    }
+
+    /// Lower the check used by non-greedy repetitions to stop as soon as the following pattern
+    /// would match. The following pattern is run against a throwaway output so that it cannot
+    /// modify the real one, and on a fork that does not track failures since the check failing is
+    /// not an error.
+    fn lookahead<T>(&mut self, next: Option<&Pattern<T>>) -> TokenStream
+    where
+        T: ToOwned<Owned = T> + ParserOutput,
+    {
+        match next {
+            Some(next) => {
+                let ident = self.pattern(next, None, None);
+                quote! {
+                    let mut fork = stream.lookahead();
+                    let mut scratch: Self = Default::default();
+                    if scratch.#ident(&mut fork).is_ok() {
+                        break;
+                    }
+                }
+            },
+            None => quote!(),
+        }
+    }
+}
+
+/// Whether a parameter captures only a reference to another parser with the same type as the
+/// parameter (or a `Box` of it, for recursive types), in which case it is set from the value
+/// parsed by the reference (see `Codegen::values`) instead of parsing its tokens again.
+pub fn is_value_param<T>(patterns: &[Pattern<T>], type_: &MacroStream) -> bool
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    let [Pattern::Reference(reference)] = patterns else {
+        return false;
+    };
+    let reference = reference.tokens();
+    match type_.tokens() {
+        [Token::Ident { name, .. }, Token::Punctuation { value: '<', .. }, inner @ .., Token::Punctuation { value: '>', .. }]
+            if name == "Box" =>
+        {
+            inner == reference
+        },
+        type_ => type_ == reference,
+    }
+}
+
+/// The pattern (as in a `match` arm) that matches a token equal to the given token, following
+/// `PartialEq for Token`.
+fn token_pattern(token: &Token) -> TokenStream {
+    match token {
+        Token::Ident { name, .. } => quote! {
+            macros_core::Token::Ident { name, .. } if name == #name
+        },
+        Token::Literal {
+            kind,
+            value,
+            suffix,
+            ..
+        } => {
+            let kind = kind.repr("");
+            quote! {
+                macros_core::Token::Literal { kind: #kind, value, suffix, .. } if value == #value && suffix == #suffix
+            }
+        },
+        Token::Punctuation { value, .. } => quote! {
+            macros_core::Token::Punctuation { value: #value, .. }
+        },
+        Token::Group { .. } => {
+            let token = token.repr("");
+            quote! {
+                token if *token == #token
+            }
+        },
+    }
+}
+
+/// The pattern (as in a `match` arm) that matches a token of the given class, following
+/// `TokenClass::matches`.
+fn class_pattern(class: &TokenClass) -> TokenStream {
+    match class {
+        TokenClass::Ident => quote!(macros_core::Token::Ident { .. }),
+        TokenClass::Punctuation => quote!(macros_core::Token::Punctuation { .. }),
+        TokenClass::Group => quote!(macros_core::Token::Group { .. }),
+        TokenClass::Literal => quote!(macros_core::Token::Literal { .. }),
+        TokenClass::Kind(LiteralKind::Str | LiteralKind::StrRaw(_)) => quote! {
+            macros_core::Token::Literal {
+                kind: macros_core::LiteralKind::Str | macros_core::LiteralKind::StrRaw(_),
+                ..
+            }
+        },
+        TokenClass::Kind(LiteralKind::ByteStr | LiteralKind::ByteStrRaw(_)) => quote! {
+            macros_core::Token::Literal {
+                kind: macros_core::LiteralKind::ByteStr | macros_core::LiteralKind::ByteStrRaw(_),
+                ..
+            }
+        },
+        TokenClass::Kind(kind) => {
+            let kind = kind.repr("");
+            quote!(macros_core::Token::Literal { kind: #kind, .. })
+        },
+    }
+}
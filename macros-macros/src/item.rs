@@ -0,0 +1,339 @@
+use macros_utils::{Delimiter, MacroStream, MacrosError, ParseError, Spacing, Token};
+
+/// A struct with named fields, parsed from the input of a derive macro.
+pub struct Struct {
+    pub attrs: Vec<Attribute>,
+    pub name: Token,
+    pub generics: Generics,
+    pub fields: Vec<Field>,
+}
+
+/// An outer attribute, like `#[name(...)]`.
+pub struct Attribute {
+    pub name: String,
+    pub span: proc_macro2::Span,
+    pub args: MacroStream,
+}
+
+/// The generics of a struct, split up for use in an `impl` block.
+#[derive(Default)]
+pub struct Generics {
+    /// The generic parameters with their bounds, without any defaults, like `'a, T: Clone`.
+    pub params: MacroStream,
+    /// The generic arguments, like `'a, T`.
+    pub args: MacroStream,
+    /// The where clause, including the `where` keyword.
+    pub where_clause: MacroStream,
+}
+
+/// A named field of a struct.
+pub struct Field {
+    pub name: String,
+    pub type_: MacroStream,
+}
+
+impl Generics {
+    /// The generics to put after `impl`, like `<'a, T: Clone>`.
+    pub fn impl_generics(&self) -> MacroStream {
+        wrap_angle(&self.params)
+    }
+
+    /// The generics to put after the name of the type, like `<'a, T>`.
+    pub fn type_generics(&self) -> MacroStream {
+        wrap_angle(&self.args)
+    }
+}
+
+impl Field {
+    /// Get the type inside of a wrapper type like `Option<T>` or `Vec<T>`.
+    pub fn unwrap_type(&self, wrapper: &str) -> Option<MacroStream> {
+        let mut stream = self.type_.fork();
+        let mut last = None;
+        while let Some(token) = stream.pop() {
+            match token {
+                Token::Punctuation { value: '<', .. } => break,
+                Token::Punctuation { value: ':', .. } => {},
+                Token::Ident { name, .. } => last = Some(name),
+                _ => return None,
+            }
+        }
+        match (last, stream.pop_back()) {
+            (Some(name), Some(Token::Punctuation { value: '>', .. })) if name == wrapper => {
+                Some(stream)
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Struct {
+    pub fn parse(mut stream: MacroStream) -> Result<Self, MacrosError> {
+        let attrs = parse_attrs(&mut stream);
+        skip_visibility(&mut stream);
+        match stream.pop() {
+            Some(Token::Ident { name, .. }) if name == "struct" => {},
+            Some(token) => {
+                return Err(ParseError::new(
+                    token.span(),
+                    "only structs with named fields are supported".into(),
+                )
+                .into())
+            },
+            None => return Err(ParseError::call_site("expected a struct".into()).into()),
+        }
+        let name = match stream.pop() {
+            Some(token @ Token::Ident { .. }) => token,
+            Some(token) => {
+                return Err(
+                    ParseError::new(token.span(), "expected the name of the struct".into()).into(),
+                )
+            },
+            None => {
+                return Err(ParseError::call_site("expected the name of the struct".into()).into())
+            },
+        };
+        let mut generics = Generics::default();
+        if let Some(Token::Punctuation { value: '<', .. }) = stream.peek() {
+            stream.pop();
+            let mut params = MacroStream::new();
+            let mut depth = 0;
+            let mut prev_joint_dash = false;
+            loop {
+                let token = match stream.pop() {
+                    Some(token) => token,
+                    None => {
+                        return Err(ParseError::new(name.span(), "unclosed generics".into()).into())
+                    },
+                };
+                match token {
+                    Token::Punctuation { value: '<', .. } => depth += 1,
+                    Token::Punctuation { value: '>', .. } if !prev_joint_dash => {
+                        if depth == 0 {
+                            break;
+                        }
+                        depth -= 1;
+                    },
+                    _ => {},
+                }
+                prev_joint_dash = matches!(
+                    token,
+                    Token::Punctuation {
+                        value: '-',
+                        spacing: Spacing::Joint,
+                        ..
+                    }
+                );
+                params.push_back(token);
+            }
+            for param in split_top_level(params, ',') {
+                let (param, arg) = generic_param(param);
+                if !generics.params.is_empty() {
+                    generics.params.push_back(comma());
+                    generics.args.push_back(comma());
+                }
+                generics.params.append(param);
+                generics.args.append(arg);
+            }
+        }
+        let mut where_clause = MacroStream::new();
+        let fields = loop {
+            match stream.pop() {
+                Some(Token::Group {
+                    delimiter: Delimiter::Brace,
+                    stream: fields,
+                    ..
+                }) => break fields,
+                Some(token @ Token::Group { .. })
+                | Some(token @ Token::Punctuation { value: ';', .. })
+                    if where_clause.is_empty() =>
+                {
+                    return Err(ParseError::new(
+                        token.span(),
+                        "only structs with named fields are supported".into(),
+                    )
+                    .into())
+                },
+                Some(token) => where_clause.push_back(token),
+                None => {
+                    return Err(ParseError::new(
+                        name.span(),
+                        "expected the fields of the struct".into(),
+                    )
+                    .into())
+                },
+            }
+        };
+        generics.where_clause = where_clause;
+        let fields = split_top_level(fields, ',')
+            .into_iter()
+            .map(|mut field| {
+                parse_attrs(&mut field);
+                skip_visibility(&mut field);
+                let name = match field.pop() {
+                    Some(Token::Ident { name, .. }) => name,
+                    Some(token) => {
+                        return Err(ParseError::new(
+                            token.span(),
+                            "expected the name of the field".into(),
+                        )
+                        .into())
+                    },
+                    None => return Err(ParseError::call_site("expected a field".into()).into()),
+                };
+                match field.pop() {
+                    Some(Token::Punctuation { value: ':', .. }) => {},
+                    Some(token) => {
+                        return Err(ParseError::new(
+                            token.span(),
+                            "expected a colon after the field name".into(),
+                        )
+                        .into())
+                    },
+                    None => {
+                        return Err(ParseError::call_site(
+                            "expected a colon after the field name".into(),
+                        )
+                        .into())
+                    },
+                }
+                Ok(Field { name, type_: field })
+            })
+            .collect::<Result<_, MacrosError>>()?;
+        Ok(Self {
+            attrs,
+            name,
+            generics,
+            fields,
+        })
+    }
+}
+
+fn comma() -> Token {
+    Token::Punctuation {
+        value: ',',
+        spacing: Spacing::Alone,
+        span: proc_macro2::Span::call_site(),
+    }
+}
+
+fn wrap_angle(stream: &MacroStream) -> MacroStream {
+    if stream.is_empty() {
+        return MacroStream::new();
+    }
+    let mut wrapped = MacroStream::new();
+    wrapped.push_back(Token::Punctuation {
+        value: '<',
+        spacing: Spacing::Alone,
+        span: proc_macro2::Span::call_site(),
+    });
+    wrapped.append(stream.clone());
+    wrapped.push_back(Token::Punctuation {
+        value: '>',
+        spacing: Spacing::Alone,
+        span: proc_macro2::Span::call_site(),
+    });
+    wrapped
+}
+
+/// Parse the outer attributes at the start of the stream.
+pub fn parse_attrs(stream: &mut MacroStream) -> Vec<Attribute> {
+    let mut attrs = vec![];
+    while let (Some(Token::Punctuation { value: '#', .. }), Some(Token::Group { .. })) =
+        (stream.peek(), stream.peek_at(1))
+    {
+        stream.pop();
+        if let Some(Token::Group {
+            stream: mut attr,
+            span,
+            ..
+        }) = stream.pop()
+        {
+            if let Some(Token::Ident { name, .. }) = attr.pop() {
+                let args = match attr.pop() {
+                    Some(Token::Group { stream, .. }) => stream,
+                    _ => MacroStream::new(),
+                };
+                attrs.push(Attribute { name, span, args });
+            }
+        }
+    }
+    attrs
+}
+
+/// Skip a visibility like `pub` or `pub(crate)` at the start of the stream.
+fn skip_visibility(stream: &mut MacroStream) {
+    if let Some(Token::Ident { name, .. }) = stream.peek() {
+        if name == "pub" {
+            stream.pop();
+            if let Some(Token::Group {
+                delimiter: Delimiter::Parenthesis,
+                ..
+            }) = stream.peek()
+            {
+                stream.pop();
+            }
+        }
+    }
+}
+
+/// Split a stream on a punctuation character that is not nested inside of angle brackets.
+fn split_top_level(mut stream: MacroStream, separator: char) -> Vec<MacroStream> {
+    let mut parts = vec![];
+    let mut current = MacroStream::new();
+    let mut depth = 0usize;
+    let mut prev_joint_dash = false;
+    while let Some(token) = stream.pop() {
+        match token {
+            Token::Punctuation { value: '<', .. } => depth += 1,
+            Token::Punctuation { value: '>', .. } if !prev_joint_dash => {
+                depth = depth.saturating_sub(1)
+            },
+            Token::Punctuation { value, .. } if value == separator && depth == 0 => {
+                parts.push(current);
+                current = MacroStream::new();
+                continue;
+            },
+            _ => {},
+        }
+        prev_joint_dash = matches!(
+            token,
+            Token::Punctuation {
+                value: '-',
+                spacing: Spacing::Joint,
+                ..
+            }
+        );
+        current.push_back(token);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Split a generic parameter into the parameter without its default and the argument used to
+/// refer to it.
+fn generic_param(param: MacroStream) -> (MacroStream, MacroStream) {
+    let param = split_top_level(param, '=')
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let mut arg = MacroStream::new();
+    let mut stream = param.fork();
+    match stream.pop() {
+        Some(token @ Token::Punctuation { value: '\'', .. }) => {
+            arg.push_back(token);
+            if let Some(token) = stream.pop() {
+                arg.push_back(token);
+            }
+        },
+        Some(Token::Ident { name, .. }) if name == "const" => {
+            if let Some(token) = stream.pop() {
+                arg.push_back(token);
+            }
+        },
+        Some(token) => arg.push_back(token),
+        None => {},
+    }
+    (param, arg)
+}
@@ -1,13 +1,46 @@
+#![allow(clippy::result_large_err)]
+
+mod codegen;
+mod item;
+mod precedence;
+
+use codegen::{is_value_param, Codegen};
+use item::{parse_attrs, Generics, Struct};
 use macros_utils::{
-    call_site, Delimiter, MacroStream, MacrosError, Match, Parse, ParserInput, ParserOutput, Repr,
-    Spacing, Token,
+    call_site, expand, Delimiter, MacroStream, MacrosError, Match, Parse, ParseError, ParserInput,
+    ParserOutput, Pattern, Token,
 };
+use precedence::{precedence_entries, precedence_impl};
 use proc_macro2::{Span, TokenStream};
-use proc_macro_error::{abort_call_site, proc_macro_error};
-use quote::quote;
+use quote::{quote, quote_spanned};
 
 /// Create a parser based on a set of patterns.
 ///
+/// The generated `Parse::parse` is an error if there are tokens left over once the patterns have
+/// matched, `Parse::parse_prefix` parses only the start of the stream instead.
+///
+/// Several rules can be defined at once by putting them in `rules { ... }` and separating them
+/// with `;`, each rule generates its own parser and they can refer to each other (or to any other
+/// `Parse` type) with `{ Name }#`. In a `rules` block, a `;` followed by the start of a rule (like
+/// `Name =>`) always ends the rule before it, outside of one the whole input is a single rule.
+/// Putting `#[memoize]` before the name of a rule reuses its result whenever it is parsed again at
+/// the same position, which avoids exponential backtracking (see `MacroStream::memoize`). The
+/// results are cloned out of the memo, so the type of a memoized precedence table has to be
+/// `Clone + 'static` as well.
+///
+/// A parameter with the type `macros_core::MatchTree` is set to the structured tree of its match
+/// rather than to its flattened tokens, so that the groups, choices and repetitions inside it can
+/// still be told apart.
+///
+/// A rule can also be a precedence table for expressions with operators (see `Precedence`),
+/// written as `Name => precedence { ... }` with its entries separated by `;`. There is one
+/// `atom Type => build` entry, which parses the operands with `<Type as Parse>::parse_prefix`,
+/// and an entry for each operator, `prefix "op" power => build`, `postfix "op" power => build`, or
+/// `infix "op" power left|right|none => build`, where `build` is a function or closure that
+/// builds the expression. Keyword operators (like `and`) can be written without the quotes. The
+/// type of a precedence table is not generated, it has to be defined outside of `parser!` (and
+/// implement `Debug`, `Clone`, and `Default` to be bound to parameters of other rules).
+///
 /// See `Pattern` for more information on the available patterns.
 ///
 /// # Example
@@ -18,18 +51,48 @@ use quote::quote;
 ///     NameOfParserAndOutputStruct => {}$ { {}$ : param }@
 /// }
 ///
+/// parser! {
+///     rules {
+///         Item => { {}$ : name }@ = { {}$ : value }@ ;
+///         Items => { { { Item }# : items : Item }@ },*
+///     }
+/// }
+///
+/// #[derive(Debug, Clone)]
+/// enum Expr {
+///     Num(i64),
+///     Add(Box<Expr>, Box<Expr>),
+///     Neg(Box<Expr>),
+/// }
+///
+/// impl Default for Expr {
+///     fn default() -> Self {
+///         Expr::Num(0)
+///     }
+/// }
+///
+/// parser! {
+///     rules {
+///         Atom => { { Num: { {}int : num : i64 }@ | Paren: ( { { Expr }# : expr : Box<Expr> }@ ) }& : atom : enum AtomKind }@ ;
+///         Expr => precedence {
+///             atom Atom => |a| match a.atom {
+///                 AtomKind::Num { num } => Expr::Num(num),
+///                 AtomKind::Paren { expr } => *expr,
+///             };
+///             prefix "-" 2 => |a| Expr::Neg(a.into());
+///             infix "+" 1 left => |a, b| Expr::Add(a.into(), b.into());
+///         }
+///     }
+/// }
+///
 /// let output: NameOfParserAndOutputStruct = NameOfParserAndOutputStruct::parse(
 ///     &mut proc_macro2::TokenStream::from_str("hi hello")
 ///         .unwrap()
 ///         .into(),
 /// );
-#[proc_macro_error]
 #[proc_macro]
 pub fn parser(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    match MacroStream::from_tokens(stream.into()) {
-        Err(err) => err.into_diagnostic().abort(),
-        Ok(stream) => parser_impl(stream).into(),
-    }
+    expand(stream.into(), parser_impl).into()
 }
 
 #[derive(Clone)]
@@ -44,49 +107,124 @@ impl ParserOutput for Empty {
     }
 }
 
-fn parser_impl(mut stream: MacroStream) -> TokenStream {
+fn parser_impl(stream: MacroStream) -> Result<TokenStream, MacrosError> {
+    let inputs = match rules_block(&stream) {
+        Some(block) => split_rules(block),
+        None => vec![stream],
+    };
+    let mut rules = vec![];
+    let mut errors = vec![];
+    for rule in inputs {
+        match rule_impl(rule) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(MacrosError::multiple(errors));
+    }
+    Ok(quote! {
+        #(#rules)*
+    })
+}
+
+/// Get the body of the input of `parser!` if it defines several rules, which is written as
+/// `rules { ... }` (this is never a valid rule since there is no `=>` after the name).
+fn rules_block(stream: &MacroStream) -> Option<MacroStream> {
+    let mut fork = stream.fork();
+    match (fork.pop(), fork.pop(), fork.pop()) {
+        (
+            Some(Token::Ident { name, .. }),
+            Some(Token::Group {
+                delimiter: Delimiter::Brace,
+                stream,
+                ..
+            }),
+            None,
+        ) if name == "rules" => Some(stream),
+        _ => None,
+    }
+}
+
+/// Split the body of a `rules` block into its rules, a new rule starts after any `;` that is
+/// followed by the name of a rule and `=>` (with its attributes before the name and the extra
+/// fields in braces in between if there are any).
+fn split_rules(mut stream: MacroStream) -> Vec<MacroStream> {
+    let mut rules = vec![];
+    let mut current = vec![];
+    while let Some(token) = stream.pop() {
+        if matches!(token, Token::Punctuation { value: ';', .. }) && starts_rule(&stream) {
+            rules.push(MacroStream::from_vec(std::mem::take(&mut current)));
+            continue;
+        }
+        current.push(token);
+    }
+    rules.push(MacroStream::from_vec(current));
+    rules
+}
+
+fn starts_rule(stream: &MacroStream) -> bool {
+    let mut fork = stream.fork();
+    parse_attrs(&mut fork);
+    if !matches!(fork.pop(), Some(Token::Ident { .. })) {
+        return false;
+    }
+    if let Some(Token::Group {
+        delimiter: Delimiter::Brace,
+        ..
+    }) = fork.peek()
+    {
+        fork.pop();
+    }
+    fork.starts_with_operator("=>")
+}
+
+fn rule_impl(mut stream: MacroStream) -> Result<TokenStream, MacrosError> {
+    let mut memoize = false;
+    for attr in parse_attrs(&mut stream) {
+        match attr.name.as_str() {
+            "memoize" => memoize = true,
+            _ => {
+                return Err(ParseError::new(
+                    attr.span,
+                    "only the #[memoize] attribute is supported on a parser".into(),
+                )
+                .into())
+            },
+        }
+    }
     let name = stream.pop();
     match name {
         Some(Token::Ident { name, .. }) => {
-            let mut next = stream.pop();
-            let extra_params_stream = if let Some(Token::Group {
-                delimiter: Delimiter::Brace,
-                stream: s,
-                ..
-            }) = next
-            {
-                next = stream.pop();
-                s
-            } else {
-                MacroStream::new()
+            let extra_params_stream = match stream.peek() {
+                Some(Token::Group {
+                    delimiter: Delimiter::Brace,
+                    stream: s,
+                    ..
+                }) => {
+                    let s = s.clone();
+                    stream.pop();
+                    s
+                },
+                _ => MacroStream::new(),
             };
-            let next2 = stream.pop();
-            match (next, next2) {
-                (
-                    Some(Token::Punctuation {
-                        value: '=',
-                        spacing: Spacing::Joint,
-                        ..
-                    }),
-                    Some(Token::Punctuation {
-                        value: '>',
-                        spacing: Spacing::Alone,
-                        ..
-                    }),
-                ) => {
-                    let input = match ParserInput::<Empty>::parse(&mut stream) {
-                        Err(err) => err.into_diagnostic().abort(),
-                        Ok(input) => input,
-                    };
-                    let patterns = &input
-                        .patterns
-                        .iter()
-                        .map(|p| p.repr(&name))
-                        .collect::<Vec<_>>();
+            match stream.starts_with_operator("=>") {
+                true => {
+                    stream.pop_operator();
                     let struct_name = Token::Ident {
                         name: name.clone(),
                         span: Span::call_site(),
                     };
+                    if let Some(entries) = precedence_entries(&stream) {
+                        if !extra_params_stream.is_empty() {
+                            return Err(ParseError::call_site(
+                                "a precedence table cannot have extra fields".into(),
+                            )
+                            .into());
+                        }
+                        return precedence_impl(&struct_name, entries, memoize);
+                    }
+                    let input = ParserInput::<Empty>::parse(&mut stream)?;
                     let raw_params = input
                         .params()
                         .into_iter()
@@ -100,93 +238,754 @@ fn parser_impl(mut stream: MacroStream) -> TokenStream {
                         .collect::<Vec<_>>();
                     let struct_fields =
                         raw_params.iter().map(|(ident, optional, variadic, type_)| {
-                            if *variadic {
-                                quote! {
-                                    pub #ident: Vec<#type_>,
-                                }
-                            } else if *optional {
-                                quote! {
-                                    pub #ident: Option<#type_>,
-                                }
-                            } else {
-                                quote! {
-                                    pub #ident: #type_,
-                                }
-                            }
-                        });
-                    let patterns_const = Token::Ident {
-                        name: format!("__{}_PATTERNS", name.to_ascii_uppercase()),
-                        span: call_site(),
-                    };
-                    let set_params = raw_params.iter().map(|(ident, optional, variadic, type_)| {
-                        let name = ident.ident().unwrap();
-                        let assign = if *variadic {
+                            let type_ = field_type(*optional, *variadic, type_);
                             quote! {
-                                self.#ident.push(value.0);
+                                pub #ident: #type_,
                             }
-                        } else if *optional {
-                            quote! {
-                                self.#ident = Some(value.0);
-                            }
-                        } else {
-                            quote! {
-                                self.#ident = value.0;
-                            }
-                        };
-                        quote! {
-                            #name => {
-                                match <Match as TryInto<(#type_,)>>::try_into(value) {
-                                    Ok(value) => {
-                                        #assign
-                                        Ok(())
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            },
-                        }
-                    });
-                    quote! {
+                        });
+                    let enums = nested_outputs(&input.patterns)?;
+                    let output = parser_output(
+                        &struct_name,
+                        &Generics::default(),
+                        &raw_params,
+                        &input.patterns,
+                        memoize,
+                    )?;
+                    Ok(quote! {
                         #[derive(Debug, Default, Clone)]
                         pub struct #struct_name {
                             #(#struct_fields)*
                             #extra_params_stream
                         }
 
-                        macros_core::lazy_static! {
-                            static ref #patterns_const: Vec<macros_core::Pattern<#struct_name>> = vec![
-                                #(#patterns,)*
-                            ];
-                        }
+                        #output
 
-                        #[allow(clippy::never_loop)]
-                        impl macros_core::Parse for #struct_name {
-                            fn parse(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
-                                let mut o = Default::default();
-                                let (res, o) = macros_core::Pattern::<#struct_name>::match_patterns(std::borrow::Cow::Owned(o), &#patterns_const, stream);
-                                match res {
-                                    Ok(_) => Ok(o.into_owned()),
-                                    Err(e) => Err(e),
-                                }
-                            }
-                        }
+                        #(#enums)*
+                    })
+                },
+                false => Err(ParseError::call_site(
+                    "expected => after the name of the parser".into(),
+                )
+                .into()),
+            }
+        },
+        _ => Err(ParseError::call_site("expected the name of the parser first".into()).into()),
+    }
+}
 
-                        impl macros_core::ParserOutput for #struct_name {
-                            fn set_match(&mut self, name: &str, value: macros_core::Match) -> Result<(), macros_core::MacrosError> {
-                                match name {
-                                    #(#set_params)*
-                                    _ => Ok(()),
-                                }
-                            }
+/// Derive `Parse` and `ParserOutput` for a struct based on the patterns in its `#[pattern(...)]`
+/// attribute.
+///
+/// The struct must have named fields and implement `Default`, each parameter in the patterns is
+/// bound to the field with the same name. The type of a parameter is taken from its field, so
+/// fields bound to optional parameters must be an `Option<_>` (unless the parameter has a default
+/// value, like `{... : name = default}@`) and fields bound to repeated parameters must be a
+/// `Vec<_>`. Fields without a matching parameter are left as their default.
+/// Like `parser!`, trailing input is rejected unless it is parsed with `Parse::parse_prefix`, and
+/// the parser is memoized if the struct has a `#[memoize]` attribute, which requires the struct to
+/// be `Clone + 'static` (so it cannot borrow anything).
+///
+/// See `Pattern` for more information on the available patterns.
+///
+/// # Example
+/// ```rs
+/// use macros_core::Parse;
+///
+/// #[derive(Parse, Default)]
+/// #[pattern(fn { {}$ : name }@ { ( { {}$ : args }@ ) }?)]
+/// struct Function {
+///     name: String,
+///     args: Option<macros_core::MacroStream>,
+/// }
+/// ```
+#[proc_macro_derive(Parse, attributes(pattern, memoize))]
+pub fn derive_parse(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(stream.into(), derive_parse_impl).into()
+}
 
-                            fn name() -> &'static str {
-                                #name
-                            }
+fn derive_parse_impl(stream: MacroStream) -> Result<TokenStream, MacrosError> {
+    let item = Struct::parse(stream)?;
+    let memoize = item.attrs.iter().any(|attr| attr.name == "memoize");
+    let mut attrs = item.attrs.into_iter().filter(|attr| attr.name == "pattern");
+    let mut pattern = match attrs.next() {
+        Some(attr) => attr.args,
+        None => {
+            return Err(ParseError::new(
+                item.name.span(),
+                "expected a #[pattern(...)] attribute".into(),
+            )
+            .into())
+        },
+    };
+    if let Some(attr) = attrs.next() {
+        return Err(ParseError::new(
+            attr.span,
+            "only one #[pattern(...)] attribute is allowed".into(),
+        )
+        .into());
+    }
+    let input = ParserInput::<Empty>::parse(&mut pattern)?;
+    let raw_params = input
+        .params()
+        .into_iter()
+        .map(|(name, optional, variadic, _)| {
+            let field = match item.fields.iter().find(|field| field.name == name) {
+                Some(field) => field,
+                None => {
+                    return Err(ParseError::new(
+                        item.name.span(),
+                        format!("no field named `{}` for the parameter", name).into(),
+                    )
+                    .into())
+                },
+            };
+            let span = field.type_.peek().map(|t| t.span()).unwrap_or_else(call_site);
+            let type_ = if variadic {
+                match field.unwrap_type("Vec") {
+                    Some(type_) => type_,
+                    None => {
+                        return Err(ParseError::new(
+                            span,
+                            format!(
+                                "expected the field `{}` to be a `Vec<_>` since its parameter is repeated",
+                                name
+                            )
+                            .into(),
+                        )
+                        .into())
+                    },
+                }
+            } else if optional {
+                match field.unwrap_type("Option") {
+                    Some(type_) => type_,
+                    None => {
+                        return Err(ParseError::new(
+                            span,
+                            format!(
+                                "expected the field `{}` to be an `Option<_>` since its parameter is optional",
+                                name
+                            )
+                            .into(),
+                        )
+                        .into())
+                    },
+                }
+            } else {
+                field.type_.clone()
+            };
+            let ident = Token::Ident {
+                name,
+                span: Span::call_site(),
+            };
+            Ok((ident, optional, variadic, type_))
+        })
+        .collect::<Result<Vec<_>, MacrosError>>()?;
+    let output = parser_output(
+        &item.name,
+        &item.generics,
+        &raw_params,
+        &input.patterns,
+        memoize,
+    )?;
+    let enums = nested_outputs(&input.patterns)?;
+    // checked separately so that the error points at the struct instead of at the derive
+    let memoized = match memoize {
+        true => {
+            let name = &item.name;
+            let impl_generics = item.generics.impl_generics();
+            let type_generics = item.generics.type_generics();
+            let where_clause = &item.generics.where_clause;
+            quote_spanned! {name.span()=>
+                #[allow(dead_code)]
+                const _: () = {
+                    fn memoized<T: Clone + 'static>() {}
+                    fn assert_memoized #impl_generics () #where_clause {
+                        memoized::<#name #type_generics>();
+                    }
+                };
+            }
+        },
+        false => quote!(),
+    };
+    Ok(quote! {
+        #output
+
+        #memoized
+
+        #(#enums)*
+    })
+}
+
+/// Generate the matching functions and the `Parse` and `ParserOutput` implementations for the
+/// output type of a parser, memoizing `Parse::parse_prefix` if `memoize` is set (see
+/// `MacroStream::memoize`).
+fn parser_output(
+    name: &Token,
+    generics: &Generics,
+    params: &[(Token, bool, bool, MacroStream)],
+    patterns: &[Pattern<Empty>],
+    memoize: bool,
+) -> Result<TokenStream, MacrosError> {
+    let impl_generics = generics.impl_generics();
+    let type_generics = generics.type_generics();
+    let where_clause = &generics.where_clause;
+    let mut codegen = Codegen::default();
+    codegen.values(assign_values(
+        params,
+        patterns,
+        |ident, optional, variadic| {
+            assign(quote! { self.#ident }, optional, variadic, quote!(value))
+        },
+    ));
+    let entry = codegen.sequence(patterns);
+    let functions = codegen.finish()?;
+    let maps = patterns
+        .iter()
+        .flat_map(|pattern| pattern.maps())
+        .collect::<Vec<_>>();
+    let set_params = params.iter().map(|(ident, optional, variadic, type_)| {
+        let name = ident.ident().unwrap();
+        let map = maps
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, map)| map);
+        let set = set_param(quote! { self.#ident }, *optional, *variadic, type_, map);
+        quote! {
+            #name => #set,
+        }
+    });
+    let set_trees = params
+        .iter()
+        .filter(|(ident, _, _, type_)| {
+            is_tree_param(type_) && !maps.iter().any(|(name, _)| name == ident.ident().unwrap())
+        })
+        .map(|(ident, optional, variadic, _)| {
+            let name = ident.ident().unwrap();
+            let set = set_tree(quote! { self.#ident }, *optional, *variadic);
+            quote! {
+                #name => #set,
+            }
+        })
+        .collect::<Vec<_>>();
+    let set_tree = match set_trees.is_empty() {
+        true => quote!(),
+        false => quote! {
+            fn set_tree(&mut self, name: &str, tree: macros_core::MatchTree) -> Result<(), macros_core::MacrosError> {
+                match name {
+                    #(#set_trees)*
+                    _ => macros_core::ParserOutput::set_match(self, name, tree.into()),
+                }
+            }
+        },
+    };
+    // parameters with a default are set to it up front, so that it is there for validators and
+    // stays there if the parameter does not match
+    let defaults = patterns
+        .iter()
+        .flat_map(|pattern| pattern.defaults())
+        .map(|(name, default)| {
+            let ident = Token::Ident {
+                name,
+                span: Span::call_site(),
+            };
+            quote! {
+                output.#ident = #default;
+            }
+        });
+    let parse_prefix = quote! {
+        let mut output: Self = Default::default();
+        #(#defaults)*
+        output.#entry(stream)?;
+        Ok(output)
+    };
+    let parse_prefix = match memoize {
+        true => quote! {
+            stream.memoize(|stream| {
+                #parse_prefix
+            })
+        },
+        false => parse_prefix,
+    };
+    let name_str = name.ident().unwrap();
+    Ok(quote! {
+        // `MacrosError` is too large for `clippy::result_large_err`, which the users of the
+        // parser should not have to allow themselves
+        #[allow(clippy::never_loop, clippy::result_large_err)]
+        impl #impl_generics #name #type_generics #where_clause {
+            #(#functions)*
+        }
+
+        #[allow(clippy::result_large_err)]
+        impl #impl_generics macros_core::Parse for #name #type_generics #where_clause {
+            fn parse(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| {
+                    let output = Self::parse_prefix(stream)?;
+                    stream.expect_end()?;
+                    Ok(output)
+                })
+            }
+
+            fn parse_prefix(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| {
+                    #parse_prefix
+                })
+            }
+        }
+
+        #[allow(clippy::result_large_err)]
+        impl #impl_generics macros_core::ParserOutput for #name #type_generics #where_clause {
+            fn set_match(&mut self, name: &str, value: macros_core::Match) -> Result<(), macros_core::MacrosError> {
+                match name {
+                    #(#set_params)*
+                    _ => Ok(()),
+                }
+            }
+
+            #set_tree
+
+            fn name() -> &'static str {
+                #name_str
+            }
+        }
+    })
+}
+
+/// Generate the enums for the named choices in the patterns (see `Pattern::NamedChoice`) and the
+/// structs for the nested repetitions (see `Pattern::nested_struct`).
+fn nested_outputs(patterns: &[Pattern<Empty>]) -> Result<Vec<TokenStream>, MacrosError> {
+    let mut outputs = vec![];
+    for pattern in patterns {
+        if let Some((_, type_, body)) = pattern.nested_struct() {
+            outputs.push(struct_output(&type_, body)?);
+            continue;
+        }
+        match pattern {
+            Pattern::Parameter(patterns, _, type_, _, _) => match patterns.as_slice() {
+                [Pattern::NamedChoice(choices)] => outputs.push(enum_output(type_, choices)?),
+                patterns => outputs.extend(nested_outputs(patterns)?),
+            },
+            Pattern::Optional(patterns)
+            | Pattern::Recover(patterns, _)
+            | Pattern::ZeroOrMore(patterns, _)
+            | Pattern::OneOrMore(patterns, _)
+            | Pattern::Repeat(patterns, _, _, _)
+            | Pattern::Separated(patterns, _, _, _)
+            | Pattern::Lookahead(patterns, _)
+            | Pattern::Group(_, patterns) => outputs.extend(nested_outputs(patterns)?),
+            Pattern::Choice(choices) => {
+                for patterns in choices {
+                    outputs.extend(nested_outputs(patterns)?);
+                }
+            },
+            Pattern::NamedChoice(choices) => {
+                for (_, patterns) in choices {
+                    outputs.extend(nested_outputs(patterns)?);
+                }
+            },
+            Pattern::Token(_)
+            | Pattern::Operator(_)
+            | Pattern::Any
+            | Pattern::End
+            | Pattern::Rest
+            | Pattern::Cut
+            | Pattern::Reference(_)
+            | Pattern::Class(_)
+            | Pattern::Validator(_, _) => {},
+        }
+    }
+    Ok(outputs)
+}
+
+/// Get the names of the parameters in the patterns that are set from the value parsed by a
+/// reference (see `codegen::is_value_param`) or from the enum built by a named choice instead of
+/// from their tokens.
+fn value_params(patterns: &[Pattern<Empty>]) -> Vec<String> {
+    let mut values = vec![];
+    for pattern in patterns {
+        // the parameters of the body of a nested struct belong to the struct, and the parameter
+        // itself is set to the values parsed by its parser
+        if let Some((name, _, _)) = pattern.nested_struct() {
+            values.push(name.to_string());
+            continue;
+        }
+        match pattern {
+            Pattern::Parameter(patterns, name, type_, _, map) => {
+                let named_choice = matches!(patterns.as_slice(), [Pattern::NamedChoice(_)]);
+                if map.is_none() && (named_choice || is_value_param(patterns, type_)) {
+                    values.push(name.clone());
+                }
+                values.extend(value_params(patterns));
+            },
+            Pattern::Optional(patterns)
+            | Pattern::Recover(patterns, _)
+            | Pattern::ZeroOrMore(patterns, _)
+            | Pattern::OneOrMore(patterns, _)
+            | Pattern::Repeat(patterns, _, _, _)
+            | Pattern::Separated(patterns, _, _, _)
+            | Pattern::Group(_, patterns) => values.extend(value_params(patterns)),
+            Pattern::Choice(choices) => {
+                for patterns in choices {
+                    values.extend(value_params(patterns));
+                }
+            },
+            _ => {},
+        }
+    }
+    values
+}
+
+/// Generate a struct with the parameters of the body of a nested repetition as its fields, along
+/// with its matching functions and the `Parse` and `ParserOutput` implementations.
+fn struct_output(
+    name: &MacroStream,
+    patterns: &[Pattern<Empty>],
+) -> Result<TokenStream, MacrosError> {
+    let name = name.peek().unwrap();
+    let params = patterns
+        .iter()
+        .flat_map(|pattern| pattern.params())
+        .map(|(name, optional, variadic, type_)| {
+            let ident = Token::Ident {
+                name,
+                span: Span::call_site(),
+            };
+            (ident, optional, variadic, type_)
+        })
+        .collect::<Vec<_>>();
+    let fields = params.iter().map(|(ident, optional, variadic, type_)| {
+        let type_ = field_type(*optional, *variadic, type_);
+        quote! {
+            pub #ident: #type_,
+        }
+    });
+    let output = parser_output(name, &Generics::default(), &params, patterns, false)?;
+    let nested = nested_outputs(patterns)?;
+    Ok(quote! {
+        #[derive(Debug, Default, Clone)]
+        pub struct #name {
+            #(#fields)*
+        }
+
+        #output
+
+        #(#nested)*
+    })
+}
+
+/// Generate an enum with a variant for each alternative of a named choice, along with its
+/// matching functions and the `Parse` and `ParserOutput` implementations.
+fn enum_output(
+    name: &MacroStream,
+    choices: &[(String, Vec<Pattern<Empty>>)],
+) -> Result<TokenStream, MacrosError> {
+    let name_str = name.to_string();
+    let mut codegen = Codegen::default();
+    let mut enums = vec![];
+    let variants = choices
+        .iter()
+        .map(|(variant, patterns)| {
+            let variant = Token::Ident {
+                name: variant.clone(),
+                span: Span::call_site(),
+            };
+            let params = patterns
+                .iter()
+                .flat_map(|pattern| pattern.params())
+                .map(|(name, optional, variadic, type_)| {
+                    let ident = Token::Ident {
+                        name,
+                        span: Span::call_site(),
+                    };
+                    (ident, optional, variadic, type_)
+                })
+                .collect::<Vec<_>>();
+            codegen.values(assign_values(
+                &params,
+                patterns,
+                |ident, optional, variadic| {
+                    let assign = assign(quote! { (*#ident) }, optional, variadic, quote!(value));
+                    quote! {
+                        if let Self::#variant { #ident, .. } = self {
+                            #assign
                         }
                     }
                 },
-                _ => abort_call_site!("expected => after the name of the parser"),
+            ));
+            let entry = codegen.sequence(patterns);
+            enums.extend(nested_outputs(patterns)?);
+            Ok((variant, params, entry))
+        })
+        .collect::<Result<Vec<_>, MacrosError>>()?;
+    let functions = codegen.finish()?;
+    let definitions = variants.iter().map(|(variant, params, _)| {
+        if params.is_empty() {
+            return quote! { #variant, };
+        }
+        let fields = params.iter().map(|(ident, optional, variadic, type_)| {
+            let type_ = field_type(*optional, *variadic, type_);
+            quote! {
+                #ident: #type_,
+            }
+        });
+        quote! {
+            #variant {
+                #(#fields)*
+            },
+        }
+    });
+    let constructors = variants
+        .iter()
+        .zip(choices)
+        .map(|((variant, params, _), (_, patterns))| {
+            if params.is_empty() {
+                return quote! { Self::#variant };
+            }
+            let defaults = patterns
+                .iter()
+                .flat_map(|pattern| pattern.defaults())
+                .collect::<Vec<_>>();
+            let fields = params.iter().map(|(ident, _, _, _)| {
+                match defaults
+                    .iter()
+                    .find(|(name, _)| name == ident.ident().unwrap())
+                {
+                    Some((_, default)) => quote!(#ident: #default),
+                    None => quote!(#ident: Default::default()),
+                }
+            });
+            quote! {
+                Self::#variant {
+                    #(#fields,)*
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let default = &constructors[0];
+    // each alternative is matched by building its variant, so that a parameter with the enum as
+    // its type is set while its choice is matched instead of parsing the tokens of the choice again
+    let alternatives =
+        variants
+            .iter()
+            .zip(&constructors)
+            .enumerate()
+            .map(|(i, ((_, _, entry), constructor))| {
+                quote! {
+                    let mut output = #constructor;
+                    let mut fork = stream.choice();
+                    match output.#entry(&mut fork) {
+                        Ok(m) => {
+                            stream.unfork(fork);
+                            return Ok((output, macros_core::MatchTree::Choice(#i, Box::new(m))));
+                        },
+                        Err(e) if fork.is_cut() => return Err(e),
+                        Err(_) => {},
+                    }
+                }
+            });
+    let set_params =
+        variants
+            .iter()
+            .zip(choices)
+            .flat_map(|((variant, params, _), (_, patterns))| {
+                let maps = patterns
+                    .iter()
+                    .flat_map(|pattern| pattern.maps())
+                    .collect::<Vec<_>>();
+                params
+                    .iter()
+                    .map(move |(ident, optional, variadic, type_)| {
+                        let name = ident.ident().unwrap();
+                        let map = maps
+                            .iter()
+                            .find(|(param, _)| param == name)
+                            .map(|(_, map)| map);
+                        let set = set_param(quote! { (*#ident) }, *optional, *variadic, type_, map);
+                        quote! {
+                            (Self::#variant { #ident, .. }, #name) => #set,
+                        }
+                    })
+            });
+    let set_trees = variants
+        .iter()
+        .flat_map(|(variant, params, _)| {
+            params
+                .iter()
+                .filter(|(_, _, _, type_)| is_tree_param(type_))
+                .map(move |(ident, optional, variadic, _)| {
+                    let name = ident.ident().unwrap();
+                    let set = set_tree(quote! { (*#ident) }, *optional, *variadic);
+                    quote! {
+                        (Self::#variant { #ident, .. }, #name) => #set,
+                    }
+                })
+        })
+        .collect::<Vec<_>>();
+    let set_tree = match set_trees.is_empty() {
+        true => quote!(),
+        false => quote! {
+            fn set_tree(&mut self, name: &str, tree: macros_core::MatchTree) -> Result<(), macros_core::MacrosError> {
+                match (&mut *self, name) {
+                    #(#set_trees)*
+                    _ => macros_core::ParserOutput::set_match(self, name, tree.into()),
+                }
             }
         },
-        _ => abort_call_site!("expected the name of the parser first"),
+    };
+    Ok(quote! {
+        #[derive(Debug, Clone)]
+        pub enum #name {
+            #(#definitions)*
+        }
+
+        impl Default for #name {
+            fn default() -> Self {
+                #default
+            }
+        }
+
+        #[allow(clippy::never_loop, clippy::result_large_err)]
+        impl #name {
+            #(#functions)*
+
+            #[doc(hidden)]
+            fn __macros_choice(stream: &mut macros_core::MacroStream) -> Result<(Self, macros_core::MatchTree), macros_core::MacrosError> {
+                #(#alternatives)*
+                Err(stream.error(macros_core::ParseErrorKind::NoMatchingChoice).into())
+            }
+        }
+
+        #[allow(clippy::result_large_err)]
+        impl macros_core::Parse for #name {
+            fn parse(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| {
+                    let output = Self::parse_prefix(stream)?;
+                    stream.expect_end()?;
+                    Ok(output)
+                })
+            }
+
+            fn parse_prefix(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| Self::__macros_choice(stream).map(|(output, _)| output))
+            }
+        }
+
+        #[allow(clippy::result_large_err)]
+        impl macros_core::ParserOutput for #name {
+            #[allow(clippy::match_single_binding)]
+            fn set_match(&mut self, name: &str, value: macros_core::Match) -> Result<(), macros_core::MacrosError> {
+                match (self, name) {
+                    #(#set_params)*
+                    _ => Ok(()),
+                }
+            }
+
+            #set_tree
+
+            fn name() -> &'static str {
+                #name_str
+            }
+        }
+
+        #(#enums)*
+    })
+}
+
+/// The type of the field for a parameter.
+fn field_type(optional: bool, variadic: bool, type_: &MacroStream) -> TokenStream {
+    if variadic {
+        quote! { Vec<#type_> }
+    } else if optional {
+        quote! { Option<#type_> }
+    } else {
+        quote! { #type_ }
+    }
+}
+
+/// Convert a match into the type of a parameter, with its mapping function if it has one, and
+/// assign it to `place`.
+fn set_param(
+    place: TokenStream,
+    optional: bool,
+    variadic: bool,
+    type_: &MacroStream,
+    map: Option<&MacroStream>,
+) -> TokenStream {
+    let assign = assign(place, optional, variadic, quote!(value.0));
+    let convert = match map {
+        Some(map) => quote! {
+            {
+                let map: fn(
+                    macros_core::Match,
+                    macros_core::Span,
+                ) -> Result<#type_, macros_core::MacrosError> = #map;
+                let span = value.span();
+                map(value, span).map(|value| (value,))
+            }
+        },
+        None => quote! {
+            <macros_core::Match as TryInto<(#type_,)>>::try_into(value)
+        },
+    };
+    quote! {
+        match #convert {
+            Ok(value) => {
+                #assign
+                Ok(())
+            }
+            Err(e) => Err(e.forget_position()),
+        }
+    }
+}
+
+/// Generate the code to assign an already parsed `value` to each parameter of the patterns that is
+/// set from a value instead of from its match (see `Codegen::values`), with `assign_to` generating
+/// the assignment to the field of a parameter.
+fn assign_values(
+    params: &[(Token, bool, bool, MacroStream)],
+    patterns: &[Pattern<Empty>],
+    assign_to: impl Fn(&Token, bool, bool) -> TokenStream,
+) -> Vec<(String, TokenStream)> {
+    let values = value_params(patterns);
+    params
+        .iter()
+        .filter(|(ident, _, _, _)| values.iter().any(|v| v == ident.ident().unwrap()))
+        .map(|(ident, optional, variadic, _)| {
+            let name = ident.ident().unwrap().to_string();
+            (name, assign_to(ident, *optional, *variadic))
+        })
+        .collect()
+}
+
+/// Generate the code to set a parameter to the structured tree of its match (`tree`), see
+/// `ParserOutput::set_tree`.
+fn set_tree(place: TokenStream, optional: bool, variadic: bool) -> TokenStream {
+    let assign = assign(place, optional, variadic, quote!(tree));
+    quote! {
+        {
+            #assign
+            Ok(())
+        }
+    }
+}
+
+/// Whether a parameter keeps the structured tree of its match instead of its flat `Match`, which
+/// is the case when its type is `MatchTree` (see `ParserOutput::set_tree`).
+fn is_tree_param(type_: &MacroStream) -> bool {
+    matches!(type_.tokens().last(), Some(Token::Ident { name, .. }) if name == "MatchTree")
+}
+
+fn assign(place: TokenStream, optional: bool, variadic: bool, value: TokenStream) -> TokenStream {
+    if variadic {
+        quote! {
+            #place.push(#value);
+        }
+    } else if optional {
+        quote! {
+            #place = Some(#value);
+        }
+    } else {
+        quote! {
+            #place = #value;
+        }
     }
 }
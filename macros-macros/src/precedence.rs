@@ -0,0 +1,191 @@
+use macros_utils::{Delimiter, MacroStream, MacrosError, ParseError, Token};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// Get the entries of a rule that is a precedence table, which is a rule whose body is
+/// `precedence { ... }` (this is never a valid pattern since the group has no suffix).
+pub fn precedence_entries(stream: &MacroStream) -> Option<MacroStream> {
+    let mut fork = stream.fork();
+    match (fork.pop(), fork.pop(), fork.pop()) {
+        (
+            Some(Token::Ident { name, .. }),
+            Some(Token::Group {
+                delimiter: Delimiter::Brace,
+                stream,
+                ..
+            }),
+            None,
+        ) if name == "precedence" => Some(stream),
+        _ => None,
+    }
+}
+
+/// Generate the `Parse` implementation of a rule that is a precedence table (see `Precedence`),
+/// where `name` is the type of the expressions, which has to be defined outside of `parser!`.
+///
+/// The table has one entry for its atoms and one for each operator, separated by `;`:
+/// - `atom Type => build` parses the atoms with `<Type as Parse>::parse_prefix`
+/// - `prefix "op" power => build`
+/// - `infix "op" power left|right|none => build`
+/// - `postfix "op" power => build`
+///
+/// where `build` is a function or closure turning the atom or the operands into an expression.
+/// Keyword operators can be written without the quotes.
+pub fn precedence_impl(
+    name: &Token,
+    mut entries: MacroStream,
+    memoize: bool,
+) -> Result<TokenStream, MacrosError> {
+    let mut atom = None;
+    let mut operators = vec![];
+    while !entries.is_empty() {
+        let mut entry = vec![];
+        while let Some(token) = entries.pop() {
+            if let Token::Punctuation { value: ';', .. } = token {
+                break;
+            }
+            entry.push(token);
+        }
+        let mut entry = MacroStream::from_vec(entry);
+        let kind = match entry.pop() {
+            Some(Token::Ident { name, span }) => (name, span),
+            Some(token) => return Err(expected_entry(token.span())),
+            None => continue,
+        };
+        match kind.0.as_str() {
+            "atom" => {
+                let mut type_ = vec![];
+                while !entry.is_empty() && !entry.starts_with_operator("=>") {
+                    type_.push(entry.pop().unwrap());
+                }
+                if type_.is_empty() {
+                    return Err(
+                        ParseError::new(kind.1, "expected the type of the atoms".into()).into(),
+                    );
+                }
+                if atom.is_some() {
+                    return Err(ParseError::new(
+                        kind.1,
+                        "a precedence table can only have one atom".into(),
+                    )
+                    .into());
+                }
+                let type_ = MacroStream::from_vec(type_);
+                let build = build(&mut entry, kind.1)?;
+                atom = Some(quote! {
+                    let atom = <#type_ as macros_core::Parse>::parse_prefix(stream)?;
+                    let build: &dyn Fn(#type_) -> Self = &(#build);
+                    Ok(build(atom))
+                });
+            },
+            "prefix" | "postfix" => {
+                let method = Token::Ident {
+                    name: kind.0.clone(),
+                    span: kind.1,
+                };
+                let operator = operator(&mut entry, kind.1)?;
+                let power = entry.parse::<u8>()?;
+                let build = build(&mut entry, kind.1)?;
+                operators.push(quote! {
+                    .#method(#operator, #power, #build)
+                });
+            },
+            "infix" => {
+                let operator = operator(&mut entry, kind.1)?;
+                let power = entry.parse::<u8>()?;
+                let associativity = match entry.pop() {
+                    Some(Token::Ident { name, .. }) if name == "left" => quote!(Left),
+                    Some(Token::Ident { name, .. }) if name == "right" => quote!(Right),
+                    Some(Token::Ident { name, .. }) if name == "none" => quote!(None),
+                    token => {
+                        return Err(ParseError::new(
+                            token.map(|t| t.span()).unwrap_or(kind.1),
+                            "expected the associativity of the operator (left, right, or none)"
+                                .into(),
+                        )
+                        .into())
+                    },
+                };
+                let build = build(&mut entry, kind.1)?;
+                operators.push(quote! {
+                    .infix(#operator, #power, macros_core::Associativity::#associativity, #build)
+                });
+            },
+            _ => return Err(expected_entry(kind.1)),
+        }
+    }
+    let Some(atom) = atom else {
+        return Err(ParseError::new(
+            name.span(),
+            "a precedence table needs an atom (like atom Type => build)".into(),
+        )
+        .into());
+    };
+    let parse_prefix = quote! {
+        macros_core::Precedence::<Self>::new(|stream| {
+            #atom
+        })
+        #(#operators)*
+        .parse(stream)
+    };
+    let parse_prefix = match memoize {
+        true => quote! {
+            stream.memoize(|stream| {
+                #parse_prefix
+            })
+        },
+        false => parse_prefix,
+    };
+    Ok(quote! {
+        #[allow(clippy::result_large_err)]
+        impl macros_core::Parse for #name {
+            fn parse(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| {
+                    let output = Self::parse_prefix(stream)?;
+                    stream.expect_end()?;
+                    Ok(output)
+                })
+            }
+
+            fn parse_prefix(stream: &mut macros_core::MacroStream) -> Result<Self, macros_core::MacrosError> {
+                stream.track(|stream| {
+                    #parse_prefix
+                })
+            }
+        }
+    })
+}
+
+fn expected_entry(span: proc_macro2::Span) -> MacrosError {
+    ParseError::new(
+        span,
+        "expected an entry of the precedence table (atom, prefix, infix, or postfix)".into(),
+    )
+    .into()
+}
+
+/// Parse the operator of an entry, either a string literal like `"+"` or a keyword like `and`.
+fn operator(entry: &mut MacroStream, span: proc_macro2::Span) -> Result<String, MacrosError> {
+    match entry.peek() {
+        Some(Token::Literal { .. } | Token::Ident { .. }) => entry.parse::<String>(),
+        token => Err(ParseError::new(
+            token.map(|t| t.span()).unwrap_or(span),
+            "expected the operator as a string (like \"+\") or a keyword".into(),
+        )
+        .into()),
+    }
+}
+
+/// Parse the `=> build` at the end of an entry.
+fn build(entry: &mut MacroStream, span: proc_macro2::Span) -> Result<MacroStream, MacrosError> {
+    let arrow = entry.starts_with_operator("=>");
+    entry.pop_operator();
+    if !arrow || entry.is_empty() {
+        return Err(ParseError::new(
+            span,
+            "expected => and a function to build the expression".into(),
+        )
+        .into());
+    }
+    Ok(entry.clone())
+}
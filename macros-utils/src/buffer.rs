@@ -0,0 +1,216 @@
+use std::rc::Rc;
+
+use crate::Token;
+
+/// A shared, reference-counted buffer of tokens.
+///
+/// Cloning a `TokenBuffer` only increments the reference count, the tokens themselves are never
+/// copied unless the buffer is modified while shared.
+#[derive(Clone, Debug, Default)]
+pub struct TokenBuffer {
+    tokens: Rc<Vec<Token>>,
+}
+
+/// A lightweight cursor into a `TokenBuffer`, made up of the shared buffer and the range of tokens
+/// that have not been consumed yet.
+///
+/// Copying a cursor is O(1), which makes forking a `MacroStream` (and therefore backtracking in a
+/// `Pattern`) cheap regardless of how many tokens are left.
+#[derive(Clone, Default)]
+pub struct Cursor {
+    buffer: TokenBuffer,
+    pos: usize,
+    end: usize,
+}
+
+impl TokenBuffer {
+    /// Create a new buffer from a list of tokens.
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens: Rc::new(tokens),
+        }
+    }
+
+    /// Create a cursor pointing at the start of the buffer.
+    pub fn begin(&self) -> Cursor {
+        Cursor {
+            buffer: self.clone(),
+            pos: 0,
+            end: self.tokens.len(),
+        }
+    }
+
+    /// Get the tokens in the buffer.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Determine if two buffers share the same underlying tokens.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.tokens, &other.tokens)
+    }
+}
+
+impl From<Vec<Token>> for TokenBuffer {
+    fn from(tokens: Vec<Token>) -> Self {
+        Self::new(tokens)
+    }
+}
+
+impl Cursor {
+    /// Get the buffer the cursor points into.
+    pub fn buffer(&self) -> &TokenBuffer {
+        &self.buffer
+    }
+
+    /// Get the position of the cursor in its buffer.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Get the tokens that have not been consumed yet.
+    pub fn tokens(&self) -> &[Token] {
+        &self.buffer.tokens[self.pos..self.end]
+    }
+
+    /// Get the token at the given index from the cursor.
+    pub fn get(&self, i: usize) -> Option<&Token> {
+        self.tokens().get(i)
+    }
+
+    /// Get the number of tokens that have not been consumed yet.
+    pub fn len(&self) -> usize {
+        self.end - self.pos
+    }
+
+    /// Determine if all the tokens have been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.end
+    }
+
+    /// Consume the next token, returning a reference to it.
+    pub fn pop(&mut self) -> Option<&Token> {
+        if self.is_empty() {
+            return None;
+        }
+        self.pos += 1;
+        self.buffer.tokens.get(self.pos - 1)
+    }
+
+    /// Consume the last token, returning a reference to it.
+    pub fn pop_back(&mut self) -> Option<&Token> {
+        if self.is_empty() {
+            return None;
+        }
+        self.end -= 1;
+        self.buffer.tokens.get(self.end)
+    }
+
+    /// Skip the given number of tokens, or to the end if there are less than that left.
+    pub fn advance(&mut self, n: usize) {
+        self.pos = self.end.min(self.pos + n);
+    }
+
+    /// Put a token in front of the cursor. If a token has been consumed, the cursor steps back
+    /// over it and the token is written to its slot, so the buffer is only copied if it is shared.
+    pub(crate) fn push_front(&mut self, token: Token) {
+        if self.pos == 0 {
+            return self.modify(|tokens| tokens.insert(0, token));
+        }
+        self.pos -= 1;
+        Rc::make_mut(&mut self.buffer.tokens)[self.pos] = token;
+    }
+
+    /// Modify the tokens that have not been consumed yet, copying them out of the buffer first if
+    /// it is shared.
+    pub(crate) fn modify<R>(&mut self, f: impl FnOnce(&mut Vec<Token>) -> R) -> R {
+        let tokens = Rc::make_mut(&mut self.buffer.tokens);
+        tokens.truncate(self.end);
+        tokens.drain(..self.pos);
+        let res = f(tokens);
+        self.pos = 0;
+        self.end = tokens.len();
+        res
+    }
+}
+
+/// Only the tokens that have not been consumed yet are shown, not the whole buffer.
+impl std::fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cursor")
+            .field("tokens", &self.tokens())
+            .finish()
+    }
+}
+
+impl From<Vec<Token>> for Cursor {
+    fn from(tokens: Vec<Token>) -> Self {
+        TokenBuffer::new(tokens).begin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use proc_macro2::TokenStream;
+
+    use crate::MacroStream;
+
+    fn stream(input: &str) -> MacroStream {
+        MacroStream::from_tokens(TokenStream::from_str(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fork() {
+        let mut stream = stream("a b c");
+        let mut fork = stream.fork();
+        assert!(fork.cursor.buffer().ptr_eq(stream.cursor.buffer()));
+        fork.pop();
+        fork.pop();
+        // popping from the fork leaves the original where it was
+        assert_eq!(stream.len(), 3);
+        assert_eq!(fork, self::stream("c"));
+        stream.unfork(fork);
+        assert_eq!(stream, self::stream("c"));
+        assert_eq!(stream.cursor.position(), 2);
+    }
+
+    #[test]
+    fn push_front() {
+        let mut stream = stream("a b c");
+        let a = stream.pop().unwrap();
+        let fork = stream.fork();
+        // the buffer is shared with the fork, so it is copied before the token is written back
+        stream.push_front(a.clone());
+        assert!(!stream.cursor.buffer().ptr_eq(fork.cursor.buffer()));
+        assert_eq!(stream, self::stream("a b c"));
+        assert_eq!(fork, self::stream("b c"));
+        // otherwise the token is written to the slot it was popped from
+        let tokens = stream.cursor.buffer().tokens().as_ptr();
+        stream.pop();
+        stream.push_front(self::stream("d").pop().unwrap());
+        assert_eq!(stream.cursor.buffer().tokens().as_ptr(), tokens);
+        assert_eq!(stream.cursor.position(), 0);
+        assert_eq!(stream, self::stream("d b c"));
+        // with nothing popped the token is inserted at the start
+        stream.push_front(a);
+        assert_eq!(stream, self::stream("a d b c"));
+    }
+
+    #[test]
+    fn debug() {
+        let mut stream = stream("a b");
+        stream.pop();
+        // the consumed tokens are still in the buffer, but they are not shown
+        let b = stream.peek().unwrap().clone();
+        assert_eq!(
+            format!("{:?}", stream),
+            format!("MacroStream {{ stream: [{:?}] }}", b)
+        );
+        assert_eq!(
+            format!("{:?}", stream.cursor),
+            format!("Cursor {{ tokens: [{:?}] }}", b)
+        );
+    }
+}
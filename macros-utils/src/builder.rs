@@ -0,0 +1,371 @@
+use std::{borrow::Cow, str::FromStr};
+
+use proc_macro2::TokenStream;
+
+use crate::{
+    call_site, Delimiter, MacroStream, Match, ParserOutput, Pattern, Spacing, Token, TokenClass,
+    Trailing, ValidatorResult,
+};
+
+/// A sequence of patterns being built, see the constructors on `Pattern` (like `Pattern::ident`)
+/// for where to start.
+///
+/// The builder produces the same patterns as the `parser!` macro does for the equivalent syntax,
+/// so they can be run with `Pattern::execute` or `Pattern::match_patterns`.
+///
+/// # Example
+/// ```rs
+/// use macros_core::{Delimiter, DynamicOutput, Pattern};
+///
+/// // fn { {}$ : name }@ { ( { { {}$ : args }@ {,}? }* ) }?
+/// let patterns = Pattern::<DynamicOutput>::ident("fn")
+///     .then(Pattern::any().capture("name"))
+///     .then(
+///         Pattern::group(
+///             Delimiter::Parenthesis,
+///             Pattern::any()
+///                 .capture("args")
+///                 .then(Pattern::punct(',').optional())
+///                 .zero_or_more(),
+///         )
+///         .optional(),
+///     )
+///     .build();
+/// ```
+pub struct Sequence<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    patterns: Vec<Pattern<T>>,
+}
+
+impl<T> Pattern<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    /// Match any single token, like `{}$`.
+    pub fn any() -> Sequence<T> {
+        Self::Any.into()
+    }
+
+    /// Match a token exactly.
+    pub fn token(token: Token) -> Sequence<T> {
+        Self::Token(token).into()
+    }
+
+    /// Match the end of the input (or of the enclosing group), like `{}^`.
+    pub fn end() -> Sequence<T> {
+        Self::End.into()
+    }
+
+    /// Match the rest of the input (or of the enclosing group), like `{}rest`.
+    pub fn rest() -> Sequence<T> {
+        Self::Rest.into()
+    }
+
+    /// Commit to the current alternative once reached, like `{}!`, see `MacroStream::cut`.
+    pub fn cut() -> Sequence<T> {
+        Self::Cut.into()
+    }
+
+    /// Match any single token of the class, like `{}ident` or `{}str`.
+    pub fn class(class: TokenClass) -> Sequence<T> {
+        Self::Class(class).into()
+    }
+
+    /// Match an identifier (or keyword) with the given name.
+    pub fn ident(name: &str) -> Sequence<T> {
+        Self::token(Token::Ident {
+            name: name.to_string(),
+            span: call_site(),
+        })
+    }
+
+    /// Match a punctuation character.
+    pub fn punct(value: char) -> Sequence<T> {
+        Self::token(Token::Punctuation {
+            value,
+            spacing: Spacing::Alone,
+            span: call_site(),
+        })
+    }
+
+    /// Match an operator made up of joint punctuation, like `=>` or `::`, see
+    /// `MacroStream::starts_with_operator`.
+    pub fn operator(operator: &str) -> Sequence<T> {
+        Self::Operator(operator.to_string()).into()
+    }
+
+    /// Match a group with the given delimiter whose contents match the patterns.
+    pub fn group(delimiter: Delimiter, patterns: impl Into<Sequence<T>>) -> Sequence<T> {
+        Self::Group(delimiter, patterns.into().patterns).into()
+    }
+
+    /// Match the first of the alternatives that matches, like `{... | ...}&`.
+    pub fn choice<S>(choices: impl IntoIterator<Item = S>) -> Sequence<T>
+    where
+        S: Into<Sequence<T>>,
+    {
+        Self::Choice(choices.into_iter().map(|c| c.into().patterns).collect()).into()
+    }
+
+    /// Match the first of the named alternatives that matches, like
+    /// `{Name: ... | Name: ...}&`. This should be captured with `Sequence::capture_as` using a type
+    /// like `enum Name` for the `parser!` macro to generate an enum for it.
+    pub fn named_choice<S>(choices: impl IntoIterator<Item = (&'static str, S)>) -> Sequence<T>
+    where
+        S: Into<Sequence<T>>,
+    {
+        Self::NamedChoice(
+            choices
+                .into_iter()
+                .map(|(name, c)| (name.to_string(), c.into().patterns))
+                .collect(),
+        )
+        .into()
+    }
+}
+
+impl<T> Sequence<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    /// Create an empty sequence, which always matches.
+    pub fn new() -> Self {
+        Self { patterns: vec![] }
+    }
+
+    /// Finish building, returning the patterns in the sequence.
+    pub fn build(self) -> Vec<Pattern<T>> {
+        self.patterns
+    }
+
+    /// Match the patterns after this sequence.
+    pub fn then(mut self, next: impl Into<Sequence<T>>) -> Self {
+        self.patterns.extend(next.into().patterns);
+        self
+    }
+
+    /// Make the sequence optional, like `{...}?`.
+    pub fn optional(self) -> Self {
+        Pattern::Optional(self.patterns).into()
+    }
+
+    /// Match the sequence zero or more times, stopping as soon as the pattern after it would
+    /// match, like `{...}*`.
+    pub fn zero_or_more(self) -> Self {
+        Pattern::ZeroOrMore(self.patterns, false).into()
+    }
+
+    /// Match the sequence zero or more times, as many times as possible, like `{...}**`.
+    pub fn zero_or_more_greedy(self) -> Self {
+        Pattern::ZeroOrMore(self.patterns, true).into()
+    }
+
+    /// Match the sequence one or more times, stopping as soon as the pattern after it would
+    /// match, like `{...}+`.
+    pub fn one_or_more(self) -> Self {
+        Pattern::OneOrMore(self.patterns, false).into()
+    }
+
+    /// Match the sequence one or more times, as many times as possible, like `{...}++`.
+    pub fn one_or_more_greedy(self) -> Self {
+        Pattern::OneOrMore(self.patterns, true).into()
+    }
+
+    /// Match the sequence zero or more times with the separator (like `,` or `::`) between each
+    /// repetition, like `{...},*`.
+    pub fn zero_or_more_separated(self, separator: &str, trailing: Trailing) -> Self {
+        Pattern::Separated(self.patterns, separator.to_string(), false, trailing).into()
+    }
+
+    /// Match the sequence one or more times with the separator (like `,` or `::`) between each
+    /// repetition, like `{...},+`.
+    pub fn one_or_more_separated(self, separator: &str, trailing: Trailing) -> Self {
+        Pattern::Separated(self.patterns, separator.to_string(), true, trailing).into()
+    }
+
+    /// Match the sequence between `min` and `max` times (or at least `min` times if there is no
+    /// `max`), stopping as soon as the pattern after it would match, like `{...}{min,max}`.
+    pub fn repeat(self, min: usize, max: Option<usize>) -> Self {
+        Pattern::Repeat(self.patterns, min, max, false).into()
+    }
+
+    /// Match the sequence between `min` and `max` times (or at least `min` times if there is no
+    /// `max`), as many times as possible, like `{...}{{min,max}}`.
+    pub fn repeat_greedy(self, min: usize, max: Option<usize>) -> Self {
+        Pattern::Repeat(self.patterns, min, max, true).into()
+    }
+
+    /// Check that the sequence would match without consuming anything, like `{...}>`.
+    pub fn lookahead(self) -> Self {
+        Pattern::Lookahead(self.patterns, false).into()
+    }
+
+    /// Check that the sequence would not match without consuming anything, like `{...}!`.
+    pub fn negative_lookahead(self) -> Self {
+        Pattern::Lookahead(self.patterns, true).into()
+    }
+
+    /// Match either this sequence or the other one, like `{... | ...}&`. Chaining `or` adds more
+    /// alternatives to the same choice.
+    pub fn or(mut self, other: impl Into<Sequence<T>>) -> Self {
+        let other = other.into().patterns;
+        if let [Pattern::Choice(choices)] = self.patterns.as_mut_slice() {
+            choices.push(other);
+            return self;
+        }
+        Pattern::Choice(vec![self.patterns, other]).into()
+    }
+
+    /// Bind the match of the sequence to the parameter `name` as a `Match`, like
+    /// `{... : name}@`.
+    pub fn capture(self, name: &str) -> Self {
+        self.capture_as(name, TokenStream::from_str("macros_core::Match").unwrap())
+    }
+
+    /// Bind the match of the sequence to the parameter `name` with the given type, like
+    /// `{... : name : type}@`. The type is only used by generated parsers.
+    pub fn capture_as(self, name: &str, type_: impl Into<MacroStream>) -> Self {
+        Pattern::Parameter(self.patterns, name.to_string(), type_.into(), None, None).into()
+    }
+
+    /// Recover from the sequence failing to match by skipping to the next of the `sync` tokens,
+    /// like `{... | ...}%`, see `MacroStream::recover`.
+    pub fn recover(self, sync: impl IntoIterator<Item = Token>) -> Self {
+        Pattern::Recover(self.patterns, sync.into_iter().collect()).into()
+    }
+
+    /// Validate the match of the last pattern in the sequence, like `{...}=`. The validator can be
+    /// a function or a closure, see `validator`.
+    pub fn validate<R>(
+        mut self,
+        validator: impl for<'a> Fn(Cow<'a, T>, &Match) -> (R, Cow<'a, T>) + 'static,
+    ) -> Self
+    where
+        R: ValidatorResult,
+    {
+        self.patterns
+            .push(Pattern::Validator(None, Some(crate::validator(validator))));
+        self
+    }
+}
+
+impl<T> Default for Sequence<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<Pattern<T>> for Sequence<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    fn from(pattern: Pattern<T>) -> Self {
+        Self {
+            patterns: vec![pattern],
+        }
+    }
+}
+
+impl<T> From<Vec<Pattern<T>>> for Sequence<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    fn from(patterns: Vec<Pattern<T>>) -> Self {
+        Self { patterns }
+    }
+}
+
+impl<T> From<Sequence<T>> for Vec<Pattern<T>>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    fn from(sequence: Sequence<T>) -> Self {
+        sequence.patterns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DynamicOutput, Repr};
+
+    /// Check that the builder produces the same patterns as the syntax of `parser!`.
+    fn assert_same(built: Sequence<DynamicOutput>, pattern: &str) {
+        let parsed = Pattern::<DynamicOutput>::compile(pattern).unwrap();
+        assert_eq!(
+            built.build().repr("DynamicOutput").to_string(),
+            parsed.repr("DynamicOutput").to_string(),
+            "{}",
+            pattern
+        );
+    }
+
+    #[test]
+    fn same_as_syntax() {
+        assert_same(
+            Pattern::ident("fn")
+                .then(Pattern::any().capture("name"))
+                .then(
+                    Pattern::group(
+                        Delimiter::Parenthesis,
+                        Pattern::any()
+                            .capture("args")
+                            .then(Pattern::punct(',').optional())
+                            .zero_or_more(),
+                    )
+                    .optional(),
+                ),
+            "fn { {}$ : name }@ { ( { { {}$ : args }@ {,}? }* ) }?",
+        );
+        assert_same(
+            Pattern::ident("struct")
+                .then(Pattern::cut())
+                .then(Pattern::class(TokenClass::Ident))
+                .or(Pattern::ident("enum"))
+                .or(Pattern::end()),
+            "{ struct {}! {}ident | enum | {}^ }&",
+        );
+        assert_same(
+            Pattern::any()
+                .zero_or_more_greedy()
+                .then(Pattern::any().one_or_more())
+                .then(Pattern::any().one_or_more_greedy())
+                .then(Pattern::any().repeat(1, Some(3)))
+                .then(Pattern::any().repeat_greedy(0, None))
+                .then(Pattern::rest()),
+            "{ {}$ }** { {}$ }+ { {}$ }++ { {}$ }{1,3} { {}$ }{{0,}} {}rest",
+        );
+        assert_same(
+            Pattern::class(TokenClass::Ident)
+                .zero_or_more_separated(",", Trailing::Allow)
+                .then(Pattern::any().one_or_more_separated("::", Trailing::Forbid))
+                .then(Pattern::operator("=>")),
+            "{ {}ident },* { {}$ }::+! =>",
+        );
+        assert_same(
+            Pattern::punct(';')
+                .lookahead()
+                .then(Pattern::punct(';').negative_lookahead())
+                .then(
+                    Pattern::ident("let")
+                        .recover([Token::Punctuation {
+                            value: ';',
+                            spacing: Spacing::Alone,
+                            span: call_site(),
+                        }])
+                        .capture_as("statement", TokenStream::from_str("String").unwrap()),
+                ),
+            "{ ; }> { ; }! { { let | ; }% : statement : String }@",
+        );
+        assert_same(
+            Pattern::named_choice([("A", Pattern::ident("a")), ("B", Pattern::ident("b"))])
+                .capture_as("kind", TokenStream::from_str("K").unwrap()),
+            "{ { A: a | B: b }& : kind : enum K }@",
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use crate::{MacrosError, Match, MatchTree, ParserOutput};
+
+/// A `ParserOutput` for patterns that are built at runtime (like with `Pattern::compile`), which
+/// stores the matches of each parameter by name instead of converting them into fields. The
+/// structured tree of each match is kept as well (see `DynamicOutput::get_tree`).
+///
+/// # Example
+/// ```rs
+/// use macros_core::{DynamicOutput, Pattern};
+///
+/// let patterns = Pattern::<DynamicOutput>::compile("fn { {}$ : name }@")?;
+/// let output = Pattern::execute(&patterns, &mut stream)?;
+/// let name = output.get("name");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DynamicOutput {
+    matches: HashMap<String, Vec<Match>>,
+    trees: HashMap<String, Vec<MatchTree>>,
+}
+
+impl DynamicOutput {
+    /// Get the last match of a parameter, which is its only match unless it is repeated.
+    pub fn get(&self, name: &str) -> Option<&Match> {
+        self.matches.get(name).and_then(|matches| matches.last())
+    }
+
+    /// Get all of the matches of a parameter, in the order they were matched.
+    pub fn get_all(&self, name: &str) -> &[Match] {
+        self.matches.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the structured tree of the last match of a parameter, see `MatchTree`.
+    pub fn get_tree(&self, name: &str) -> Option<&MatchTree> {
+        self.trees.get(name).and_then(|trees| trees.last())
+    }
+
+    /// Get the structured trees of all of the matches of a parameter, in the order they were
+    /// matched.
+    pub fn get_all_trees(&self, name: &str) -> &[MatchTree] {
+        self.trees.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Determine if a parameter was matched.
+    pub fn contains(&self, name: &str) -> bool {
+        self.matches.contains_key(name)
+    }
+
+    /// Get the names of the parameters that were matched.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.matches.keys().map(String::as_str)
+    }
+}
+
+impl ParserOutput for DynamicOutput {
+    fn set_match(&mut self, k: &str, m: Match) -> Result<(), MacrosError> {
+        self.matches.entry(k.to_string()).or_default().push(m);
+        Ok(())
+    }
+
+    fn set_tree(&mut self, k: &str, tree: MatchTree) -> Result<(), MacrosError> {
+        self.set_match(k, tree.clone().into())?;
+        self.trees.entry(k.to_string()).or_default().push(tree);
+        Ok(())
+    }
+
+    fn name() -> &'static str {
+        "DynamicOutput"
+    }
+}
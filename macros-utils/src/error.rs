@@ -1,18 +1,22 @@
 use std::{error::Error, fmt::Display};
 
-use proc_macro2::Span;
-use proc_macro_error::{Diagnostic, Level};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote_spanned;
 use thiserror::Error;
 
 use crate::{Delimiter, Token};
 
-/// The error type for this crate. Can be either a `Parse(ParseError)` from this crate or a `User(Box<dyn Error + Send + Sync>)` user error.
+/// The error type for this crate. Can be either a `Parse(ParseError)` from this crate, a `User(Box<dyn Error + Send + Sync>)` user error, or `Multiple(Vec<MacrosError>)` when a parser recovered from errors (see `MacroStream::recover`).
+// parse errors are by far the most common, so they are not boxed
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Error)]
 pub enum MacrosError {
     #[error(transparent)]
     Parse(ParseError),
     #[error(transparent)]
     User(Box<dyn Error + Send + Sync>),
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))]
+    Multiple(Vec<MacrosError>),
 }
 
 impl From<Box<dyn Error + Send + Sync>> for MacrosError {
@@ -28,11 +32,44 @@ impl From<ParseError> for MacrosError {
 }
 
 impl MacrosError {
-    /// Convert the error into a `proc_macro_error::Diagnostic`.
-    pub fn into_diagnostic(self) -> Diagnostic {
+    /// Convert the error into `compile_error!` invocations, one for each error, to be returned
+    /// from a proc macro. User errors are reported at the call site.
+    pub fn to_compile_error(&self) -> TokenStream {
         match self {
-            Self::Parse(error) => error.into_diagnostic(),
-            Self::User(error) => Diagnostic::new(Level::Error, error.to_string()),
+            Self::Parse(error) => error.to_compile_error(),
+            Self::User(error) => compile_error(Span::call_site(), &error.to_string()),
+            Self::Multiple(errors) => errors.iter().map(Self::to_compile_error).collect(),
+        }
+    }
+
+    /// Combine a list of errors into one, flattening any `Self::Multiple`.
+    pub fn multiple(errors: Vec<MacrosError>) -> Self {
+        let mut flattened = vec![];
+        for error in errors {
+            match error {
+                Self::Multiple(errors) => flattened.extend(errors),
+                error => flattened.push(error),
+            }
+        }
+        match flattened.len() {
+            1 => flattened.pop().unwrap(),
+            _ => Self::Multiple(flattened),
+        }
+    }
+
+    /// Forget the position of the error, this should be done when an error crosses from one stream
+    /// to another (like when converting a `Match` to the type of a parameter) since the position
+    /// is meaningless outside of the stream it was produced in.
+    pub fn forget_position(self) -> Self {
+        match self {
+            Self::Parse(mut error) => {
+                error.position = None;
+                Self::Parse(error)
+            },
+            Self::Multiple(errors) => {
+                Self::Multiple(errors.into_iter().map(Self::forget_position).collect())
+            },
+            error => error,
         }
     }
 
@@ -46,12 +83,14 @@ impl MacrosError {
 }
 
 /// A parse error encountered while parsing a `MacroStream`.
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 pub struct ParseError {
     #[source]
     pub error: ParseErrorKind,
     pub span: Span,
-    pub level: Level,
+    /// The position in the stream being parsed where the error occurred (see
+    /// `MacroStream::path`), if the error was produced by a pattern failing to match.
+    pub position: Option<Vec<usize>>,
 }
 
 impl ParseError {
@@ -60,22 +99,24 @@ impl ParseError {
         Self {
             error,
             span,
-            level: Level::Error,
+            position: None,
         }
     }
 
     /// Create a new parse error with the given kind and the `Span::call_site()` span.
     pub fn call_site(error: ParseErrorKind) -> Self {
-        Self {
-            error,
-            span: Span::call_site(),
-            level: Level::Error,
-        }
+        Self::new(Span::call_site(), error)
+    }
+
+    /// Set the position in the stream being parsed where the error occurred.
+    pub fn at(mut self, position: Vec<usize>) -> Self {
+        self.position = Some(position);
+        self
     }
 
-    /// Convert the error into a `proc_macro_error::Diagnostic`.
-    pub fn into_diagnostic(self) -> Diagnostic {
-        Diagnostic::spanned(self.span, self.level, self.error.to_string())
+    /// Convert the error into a `compile_error!` invocation with the span of the error.
+    pub fn to_compile_error(&self) -> TokenStream {
+        compile_error(self.span, &self.error.to_string())
     }
 
     /// Add a message to the error if it is an `UnexpectedEndOfInput` error.
@@ -92,15 +133,17 @@ impl Display for ParseError {
     }
 }
 
-impl From<ParseError> for Diagnostic {
-    fn from(error: ParseError) -> Diagnostic {
-        error.into_diagnostic()
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    quote_spanned! {span=>
+        ::core::compile_error! { #message }
     }
 }
 
 /// The specific kind of parse error encountered.
 #[non_exhaustive]
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 pub enum ParseErrorKind {
     #[error("Unknown literal: {0}")]
     UnknownLiteral(String),
@@ -132,6 +175,10 @@ pub enum ParseErrorKind {
     UnexpectedEndOfInput(String),
     #[error("Expected {0:?}, but found {1:?}")]
     Expected(Token, Token),
+    #[error("{}", expected_one_of(.0))]
+    ExpectedOneOf(Vec<String>),
+    #[error("Unexpected {0}")]
+    Unexpected(String),
     #[error("No matching choice found")]
     NoMatchingChoice,
     #[error("Expected a group delimited by {0}")]
@@ -140,14 +187,122 @@ pub enum ParseErrorKind {
     InputTooLong,
     #[error("Expected one or more repetitions, but found none")]
     ExpectedRepetition,
+    #[error("Expected at most {0} repetitions")]
+    TooManyRepetitions(usize),
     #[error("Validator must have a non-validator pattern preceding it")]
     InvalidValidatorPosition,
     #[error("Validator failed with message: {0}")]
     ValidatorFailed(String),
+    #[error("Expected one of ?*+=~@&%$!>#, repetition bounds (like {{n,m}}), or a separator (like ,*) after single braces")]
+    InvalidPatternSuffix,
+    #[error("Expected one of ?*+=~@&%$!>#^ after a tilde")]
+    InvalidEscape,
+    #[error("Found an empty group, expected either an any pattern (like {{}}$), a token class (like {{}}ident) after the braces, or something in the braces")]
+    EmptyPatternGroup,
+    #[error("Expected a pattern, a colon, then an identifier (like some_pattern_here:name), found end of input")]
+    MissingParameterName,
+    #[error("Expected a pattern, a colon, then an identifier (like some_pattern_here:name), found no pattern")]
+    MissingParameterPattern,
+    #[error("Expected an identifier as the name of the parameter")]
+    InvalidParameterName,
+    #[error("Expected a colon between the name and the type of the parameter")]
+    ExpectedParameterTypeColon,
+    #[error("Expected a type after the colon, found end of input")]
+    MissingParameterType,
+    #[error("Expected a default value after the `=`")]
+    MissingParameterDefault,
+    #[error("Only a parameter that is optional (like in {{...}}? or a choice) and not repeated can have a default value")]
+    InvalidParameterDefault,
+    #[error("Expected a mapping function after the `=>`")]
+    MissingParameterMap,
+    #[error("Expected the name of the enum after `enum`")]
+    ExpectedEnumName,
+    #[error(
+        "A parameter with an enum type must contain exactly one choice (like {{A: a | B: b}}&)"
+    )]
+    InvalidEnumParameter,
+    #[error("Expected each alternative of the choice to start with the name of its variant and a colon (like Name: ...)")]
+    ExpectedVariantName,
+    #[error("Expected the name of the struct after `struct`")]
+    ExpectedStructName,
+    #[error("A parameter with a struct type must contain exactly one repetition (like {{...}}* or {{...}},*)")]
+    InvalidStructParameter,
+    #[error("Expected a pattern to recover from before the `|`")]
+    MissingRecoverPattern,
+    #[error("Unknown token class {0}, expected one of ident, punct, group, lit, str, int, float, char, byte, bytestr, or rest")]
+    UnknownTokenClass(String),
+    #[error(
+        "Expected repetition bounds like {{n}}, {{n,}}, {{,m}}, or {{n,m}} where n is at most m"
+    )]
+    InvalidRepetitionBounds,
+    #[error("Found a reference to {0}, but references to other parsers (like {{ Type }}#) are only supported by `parser!` and `#[derive(Parse)]`")]
+    UnsupportedReference(String),
+    #[error("The operator `{0}` is not associative, use parentheses to group it")]
+    NonAssociativeOperator(String),
     #[error("{0}")]
     User(String),
 }
 
+fn expected_one_of(expected: &[String]) -> String {
+    match expected {
+        [] => "Unexpected token".to_string(),
+        [expected] => format!("Expected {}", expected),
+        expected => format!("Expected one of {}", expected.join(", ")),
+    }
+}
+
+/// The furthest point a parse has reached before failing to match a pattern, and what was
+/// expected there, shared between a `MacroStream` and its forks.
+#[derive(Debug, Default)]
+pub(crate) struct Furthest {
+    failure: Option<(Vec<usize>, Span, Vec<String>)>,
+}
+
+impl Furthest {
+    /// Record that `expected` was expected at the given position.
+    pub(crate) fn record(&mut self, position: &[usize], span: Span, expected: &str) {
+        match &mut self.failure {
+            Some((p, _, e)) if p.as_slice() == position => {
+                if !e.iter().any(|e| e == expected) {
+                    e.push(expected.to_string());
+                }
+            },
+            Some((p, _, _)) if p.as_slice() > position => {},
+            _ => self.failure = Some((position.to_vec(), span, vec![expected.to_string()])),
+        }
+    }
+
+    /// Determine if a failure was recorded after the given position.
+    pub(crate) fn is_after(&self, position: &[usize]) -> bool {
+        matches!(&self.failure, Some((p, _, _)) if p.as_slice() > position)
+    }
+
+    /// Determine if the furthest failure was recorded at the given position.
+    pub(crate) fn is_at(&self, position: &[usize]) -> bool {
+        matches!(&self.failure, Some((p, _, _)) if p.as_slice() == position)
+    }
+
+    /// Add the failures recorded in another tracker.
+    pub(crate) fn merge(&mut self, other: Self) {
+        if let Some((position, span, expected)) = other.failure {
+            for expected in expected {
+                self.record(&position, span, &expected);
+            }
+        }
+    }
+
+    /// Pick the error to report for a failed parse, which is an error listing everything that was
+    /// expected at the furthest failure unless `error` occurred further along.
+    pub(crate) fn report(self, error: ParseError) -> ParseError {
+        match self.failure {
+            Some((position, span, expected)) if error.position.as_ref() <= Some(&position) => {
+                ParseError::new(span, ParseErrorKind::ExpectedOneOf(expected)).at(position)
+            },
+            _ => error,
+        }
+    }
+}
+
 impl From<String> for ParseErrorKind {
     fn from(value: String) -> Self {
         Self::User(value)
@@ -159,3 +314,34 @@ impl From<&str> for ParseErrorKind {
         Self::User(value.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use quote::quote;
+
+    use super::*;
+
+    #[test]
+    fn compile_errors() {
+        let parse = || MacrosError::from(ParseError::call_site(ParseErrorKind::InputTooLong));
+        assert_eq!(
+            parse().to_compile_error().to_string(),
+            quote!(::core::compile_error! { "Input is too long" }).to_string()
+        );
+        let user = || MacrosError::User("expected a \"name\"".into());
+        assert_eq!(
+            user().to_compile_error().to_string(),
+            quote!(::core::compile_error! { "expected a \"name\"" }).to_string()
+        );
+        // every error is reported, not only the first one
+        let multiple = MacrosError::Multiple(vec![parse(), user()]);
+        assert_eq!(
+            multiple.to_compile_error().to_string(),
+            quote! {
+                ::core::compile_error! { "Input is too long" }
+                ::core::compile_error! { "expected a \"name\"" }
+            }
+            .to_string()
+        );
+    }
+}
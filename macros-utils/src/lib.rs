@@ -1,27 +1,88 @@
+// `MacrosError` keeps the tokens it was produced from, so it is large by design
+#![allow(clippy::result_large_err)]
+
+mod buffer;
+mod builder;
+mod dynamic;
 mod error;
+mod memo;
 mod parse;
 mod parsers;
 mod pattern;
+mod precedence;
 mod repr;
 mod tokens;
-
-use std::collections::VecDeque;
-
+mod tree;
+mod validator;
+
+use std::{
+    any::TypeId,
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+#[doc(hidden)]
+pub use ::lazy_static as __lazy_static;
+pub use buffer::{Cursor, TokenBuffer};
+pub use builder::Sequence;
+pub use dynamic::DynamicOutput;
+use error::Furthest;
 pub use error::{MacrosError, ParseError, ParseErrorKind};
-pub use lazy_static::lazy_static;
+use memo::Memo;
 pub use parse::Parse;
-pub use pattern::{ParserInput, Pattern};
+pub use pattern::{ParserInput, Pattern, Trailing};
+pub use precedence::{Associativity, Precedence};
 use proc_macro2::TokenStream;
 pub use proc_macro2::{Spacing, Span};
 use quote::ToTokens;
 pub use repr::Repr;
-pub use tokens::{Delimiter, LiteralKind, Token};
+pub use tokens::{Delimiter, LiteralKind, Token, TokenClass, OPERATORS};
+pub use tree::MatchTree;
+pub use validator::{validate, validator, Validator, ValidatorResult};
+
+/// A re-export of `lazy_static::lazy_static`, which generated parsers used to store their patterns.
+#[deprecated(
+    since = "0.2.1",
+    note = "generated parsers no longer use `lazy_static`, depend on it directly instead"
+)]
+#[macro_export]
+macro_rules! lazy_static {
+    ($($tt:tt)*) => {
+        $crate::__lazy_static::lazy_static! { $($tt)* }
+    };
+}
 
 /// A stream of tokens.
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// The tokens are stored in a shared `TokenBuffer` and the stream is a `Cursor` into it, so
+/// cloning or forking a stream does not copy any tokens.
+#[derive(Clone)]
 pub struct MacroStream {
-    stream: VecDeque<Token>,
+    cursor: Cursor,
     popped: usize,
+    /// The positions of the groups this stream was entered from, see `MacroStream::enter`.
+    path: Vec<usize>,
+    /// The furthest failure while parsing, shared with forks, see `MacroStream::track`.
+    furthest: Option<Rc<RefCell<Furthest>>>,
+    /// The errors recovered from while parsing, see `MacroStream::recover`. Unlike the furthest
+    /// failure these belong to the fork they were recovered in, so that they are dropped along
+    /// with it when backtracking and kept when it is unforked.
+    recovered: Option<Rc<RefCell<Vec<MacrosError>>>>,
+    /// The results of memoized parses, shared with forks, see `MacroStream::memoize`.
+    memo: Option<Rc<RefCell<Memo>>>,
+    /// The alternative being matched, see `MacroStream::alternative`.
+    alternative: Option<Rc<Alternative>>,
+}
+
+/// The state of the alternative being matched, shared between a `MacroStream` and its forks.
+#[derive(Debug, Default)]
+struct Alternative {
+    /// Whether the alternative has been committed to, see `MacroStream::cut`.
+    cut: Cell<bool>,
+    /// Whether the alternative is inside of an alternative of a choice, in which errors are not
+    /// recovered from, see `MacroStream::choice`.
+    choice: bool,
 }
 
 /// Type alias for the result of parsing to a `MacroStream`.
@@ -35,6 +96,43 @@ pub enum Match {
     None,
 }
 
+impl Match {
+    /// Append the match to a list of matches, flattening `Self::Many` and dropping `Self::None`.
+    pub fn flatten_into(self, matches: &mut Vec<Match>) {
+        match self {
+            m @ Self::One(_) => matches.push(m),
+            Self::Many(m) => matches.extend(m),
+            Self::None => {},
+        }
+    }
+
+    /// The span of the match, from its first token to its last one where spans can be joined
+    /// (otherwise the span of the first token), or the call site if it is empty.
+    pub fn span(&self) -> Span {
+        let mut tokens = vec![];
+        self.tokens_into(&mut tokens);
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => {
+                let first = first.span();
+                first.join(last.span()).unwrap_or(first)
+            },
+            _ => call_site(),
+        }
+    }
+
+    fn tokens_into<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        match self {
+            Self::One(token) => tokens.push(token),
+            Self::Many(matches) => {
+                for m in matches {
+                    m.tokens_into(tokens);
+                }
+            },
+            Self::None => {},
+        }
+    }
+}
+
 impl Default for Match {
     fn default() -> Self {
         Self::None
@@ -71,10 +169,7 @@ impl From<Match> for Option<MacroStream> {
 impl MacroStream {
     /// Create a new empty `MacroStream`.
     pub fn new() -> Self {
-        Self {
-            stream: VecDeque::new(),
-            popped: 0,
-        }
+        Self::from_cursor(Cursor::default())
     }
 
     /// Determine how many tokens have been popped from the stream.
@@ -88,23 +183,361 @@ impl MacroStream {
         for i in stream.into_iter() {
             tokens.push_back(i);
         }
-        let mut stream = VecDeque::new();
+        let mut stream = Vec::new();
         while !tokens.is_empty() {
-            stream.push_back(Token::from_tokens(&mut tokens)?);
+            stream.push(Token::from_tokens(&mut tokens)?);
         }
-        Ok(Self { stream, popped: 0 })
+        Ok(Self::from_vec(stream))
     }
 
     pub fn from_vec(tokens: Vec<Token>) -> Self {
+        Self::from_cursor(tokens.into())
+    }
+
+    /// Create a `MacroStream` from a `Cursor`.
+    pub fn from_cursor(cursor: Cursor) -> Self {
+        Self {
+            cursor,
+            popped: 0,
+            path: vec![],
+            furthest: None,
+            recovered: None,
+            memo: None,
+            alternative: None,
+        }
+    }
+
+    /// Get the cursor the stream is reading from.
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// Get the position of the stream in its underlying `TokenBuffer`.
+    pub fn position(&self) -> usize {
+        self.cursor.position()
+    }
+
+    /// Get the full position of the stream, which is the position of each group it was entered
+    /// from followed by its own position. Comparing paths tells which of two points in the input
+    /// is further along.
+    pub fn path(&self) -> Vec<usize> {
+        let mut path = self.path.clone();
+        path.push(self.position());
+        path
+    }
+
+    /// Fork the stream of the group at the front of this stream (before popping it), sharing the
+    /// failure tracking of this stream so that failures inside of the group are reported at the
+    /// right position.
+    pub fn enter(&self, group: &Self) -> Self {
         Self {
-            stream: tokens.into(),
+            cursor: group.cursor.clone(),
             popped: 0,
+            path: self.path(),
+            furthest: self.furthest.clone(),
+            recovered: None,
+            memo: self.memo.clone(),
+            alternative: self.alternative.clone(),
+        }
+    }
+
+    /// Pop the group that was entered with `MacroStream::enter`, keeping the errors that were
+    /// recovered from inside of it.
+    pub fn leave(&mut self, group: Self) {
+        self.pop();
+        self.keep_recovered(group);
+    }
+
+    /// Fork the stream without failure tracking, for checking if a pattern would match without
+    /// the check counting as a failure. A cut in the lookahead has no effect.
+    pub fn lookahead(&self) -> Self {
+        Self {
+            furthest: None,
+            alternative: None,
+            ..self.fork()
+        }
+    }
+
+    /// Fork the stream to try an alternative (like another repetition or an optional pattern, see
+    /// `MacroStream::choice` for the alternatives of a choice), which can be committed to with a
+    /// cut, see `MacroStream::cut`.
+    pub fn alternative(&self) -> Self {
+        Self {
+            alternative: Some(Rc::new(Alternative {
+                choice: self.is_choice(),
+                ..Default::default()
+            })),
+            ..self.fork()
+        }
+    }
+
+    /// Fork the stream to try one of the alternatives of a choice (see `MacroStream::alternative`).
+    /// Errors are not recovered from while matching it (see `MacroStream::recover`), so that an
+    /// alternative that does not match fails and the next one is tried.
+    pub fn choice(&self) -> Self {
+        Self {
+            alternative: Some(Rc::new(Alternative {
+                choice: true,
+                ..Default::default()
+            })),
+            ..self.fork()
+        }
+    }
+
+    /// Determine if the stream is inside of an alternative of a choice, see `MacroStream::choice`.
+    fn is_choice(&self) -> bool {
+        self.alternative.as_ref().is_some_and(|a| a.choice)
+    }
+
+    /// Commit to the alternative being matched (see `MacroStream::alternative`), so that if it
+    /// fails the error is returned instead of trying the other alternatives (or matching fewer
+    /// repetitions). This does nothing outside of an alternative.
+    pub fn cut(&self) {
+        if let Some(alternative) = &self.alternative {
+            alternative.cut.set(true);
+        }
+    }
+
+    /// Determine if the alternative being matched has been committed to with a cut.
+    pub fn is_cut(&self) -> bool {
+        self.alternative.as_ref().is_some_and(|a| a.cut.get())
+    }
+
+    /// Record that `expected` (a description like ``"`,`"`` or `"an identifier"`) was expected at
+    /// the current position of the stream, and return the error for it.
+    pub fn expected(&self, expected: &str) -> ParseError {
+        let span = self.peek().map(|t| t.span()).unwrap_or_else(call_site);
+        let path = self.path();
+        if let Some(furthest) = &self.furthest {
+            furthest.borrow_mut().record(&path, span, expected);
+        }
+        ParseError::new(
+            span,
+            ParseErrorKind::ExpectedOneOf(vec![expected.to_string()]),
+        )
+        .at(path)
+    }
+
+    /// Determine if the furthest failure so far (see `MacroStream::track`) is at the current
+    /// position of the stream, which means that this is where parsing got stuck if it fails. This
+    /// is always false when failures are not tracked.
+    pub fn is_furthest(&self) -> bool {
+        match &self.furthest {
+            Some(furthest) => furthest.borrow().is_at(&self.path()),
+            None => false,
+        }
+    }
+
+    /// Return an `InputTooLong` error at the first token left in the stream, if there is one.
+    pub fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(_) => Err(self.error(ParseErrorKind::InputTooLong)),
+            None => Ok(()),
+        }
+    }
+
+    /// Create an error for the next token in the stream being unexpected, like when a negative
+    /// lookahead matches. The error has no position, so it is reported as is instead of being
+    /// replaced by the furthest failure (see `MacroStream::track`).
+    pub fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => {
+                ParseError::new(token.span(), ParseErrorKind::Unexpected(token.describe()))
+            },
+            None => ParseError::call_site(ParseErrorKind::Unexpected("end of input".to_string())),
+        }
+    }
+
+    /// Create an error at the current position of the stream.
+    pub fn error(&self, error: ParseErrorKind) -> ParseError {
+        ParseError::new(
+            self.peek().map(|t| t.span()).unwrap_or_else(call_site),
+            error,
+        )
+        .at(self.path())
+    }
+
+    /// Run a parser on the stream while tracking the furthest point any pattern failed to match,
+    /// including failures that were recovered from by backtracking. If the parser fails because a
+    /// pattern did not match, the error is replaced with one at the furthest failure, listing
+    /// everything that was expected there.
+    ///
+    /// Nested calls share the tracking of the outermost call.
+    pub fn track<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, MacrosError>,
+    ) -> Result<T, MacrosError> {
+        if self.furthest.is_some() {
+            return f(self);
+        }
+        let furthest = Rc::new(RefCell::new(Furthest::default()));
+        self.furthest = Some(furthest.clone());
+        let memo = self.memo.is_none();
+        if memo {
+            self.memo = Some(Default::default());
+        }
+        let res = f(self);
+        self.furthest = None;
+        if memo {
+            self.memo = None;
+        }
+        let res = match res {
+            Err(MacrosError::Parse(e)) if e.position.is_some() => {
+                Err(furthest.take().report(e).into())
+            },
+            res => res,
+        };
+        let mut errors = self.recovered.take().map(|r| r.take()).unwrap_or_default();
+        match res {
+            Ok(_) if errors.is_empty() => res,
+            Ok(_) => Err(MacrosError::multiple(errors)),
+            Err(e) => {
+                errors.push(e);
+                Err(MacrosError::multiple(errors))
+            },
+        }
+    }
+
+    /// Run a parser for a `T` on the stream, reusing the result if a `T` was already parsed at the
+    /// same position during the outermost `MacroStream::track`, which keeps grammars that
+    /// backtrack over the same input many times linear. This is used by the parsers generated for
+    /// `#[memoize]` rules.
+    ///
+    /// Results that recovered from errors (see `MacroStream::recover`) and errors other than
+    /// `MacrosError::Parse` are not reused since they cannot be replayed. The results are stored
+    /// by the `TypeId` of `T` and cloned whenever they are reused, hence `T: Clone + 'static`.
+    pub fn memoize<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, MacrosError>,
+    ) -> Result<T, MacrosError>
+    where
+        T: Clone + 'static,
+    {
+        let memo = match &self.memo {
+            Some(memo) => memo.clone(),
+            None => return f(self),
+        };
+        let key = (
+            TypeId::of::<T>(),
+            self.path(),
+            self.len(),
+            self.furthest.is_some(),
+        );
+        if let Some(res) = memo.borrow().get::<T>(&key) {
+            let (value, consumed) = res?;
+            self.pop_many(consumed);
+            return Ok(value);
+        }
+        let mut fork = self.fork();
+        let res = f(&mut fork);
+        if !matches!(&fork.recovered, Some(r) if !r.borrow().is_empty()) {
+            match &res {
+                Ok(value) => {
+                    let consumed = self.len() - fork.len();
+                    memo.borrow_mut().insert(key, Ok((value.clone(), consumed)));
+                },
+                Err(MacrosError::Parse(e)) => memo.borrow_mut().insert::<T>(key, Err(e.clone())),
+                Err(_) => {},
+            }
+        }
+        if res.is_ok() {
+            self.unfork(fork);
+        }
+        res
+    }
+
+    /// Run a parser on a fork of the stream, recovering from it failing by recording the error
+    /// and skipping ahead to the next token for which `sync` returns true (or the end of the
+    /// stream, which is the end of the enclosing group), then returning the default (like
+    /// `Match::None`).
+    ///
+    /// The recorded errors are returned together by the outermost `MacroStream::track` once
+    /// parsing is done, so that every mistake in the input is reported at once. Nothing is
+    /// recovered from when the stream is empty, when failures are not tracked (like in a
+    /// lookahead), inside an alternative of a choice (see `MacroStream::choice`), or when the
+    /// parser fails before getting past the first token (so that whatever comes after it, like
+    /// the end of a repetition, can match there instead), the error is returned instead.
+    pub fn recover<R: Default>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, MacrosError>,
+        sync: impl Fn(&Token) -> bool,
+    ) -> Result<R, MacrosError> {
+        let mut fork = self.fork();
+        let furthest = match &self.furthest {
+            Some(furthest) if !self.is_empty() && !self.is_choice() => furthest.clone(),
+            _ => {
+                let m = f(&mut fork)?;
+                self.unfork(fork);
+                return Ok(m);
+            },
+        };
+        let outer = furthest.take();
+        let res = f(&mut fork);
+        let inner = furthest.replace(outer);
+        match res {
+            Ok(m) => {
+                furthest.borrow_mut().merge(inner);
+                self.unfork(fork);
+                Ok(m)
+            },
+            Err(e) => {
+                let start = self.path();
+                let progressed = fork.len() < self.len()
+                    || inner.is_after(&start)
+                    || matches!(&e, MacrosError::Parse(e) if e.position.as_deref() > Some(&start[..]));
+                if !progressed {
+                    furthest.borrow_mut().merge(inner);
+                    return Err(e);
+                }
+                let e = match e {
+                    MacrosError::Parse(e) if e.position.is_some() => inner.report(e).into(),
+                    e => e,
+                };
+                self.recovered
+                    .get_or_insert_with(Default::default)
+                    .borrow_mut()
+                    .push(e);
+                while let Some(token) = self.peek() {
+                    if sync(token) {
+                        break;
+                    }
+                    self.pop();
+                }
+                Ok(R::default())
+            },
         }
     }
 
+    /// Move the errors recovered from in another stream into this one.
+    fn keep_recovered(&mut self, other: Self) {
+        if let Some(errors) = other.recovered {
+            self.recovered
+                .get_or_insert_with(Default::default)
+                .borrow_mut()
+                .extend(errors.take());
+        }
+    }
+
+    /// Get the tokens remaining in the stream.
+    pub fn tokens(&self) -> &[Token] {
+        self.cursor.tokens()
+    }
+
+    /// Get the tokens that a fork of this stream has consumed as a `Match`, keeping groups intact.
+    pub fn consumed(&self, fork: &Self) -> Match {
+        let consumed = self.len().saturating_sub(fork.len());
+        Match::Many(
+            self.tokens()[..consumed]
+                .iter()
+                .cloned()
+                .map(Match::One)
+                .collect(),
+        )
+    }
+
     /// Pop a token from the stream.
     pub fn pop(&mut self) -> Option<Token> {
-        self.stream.pop_front().map(|i| {
+        self.cursor.pop().cloned().map(|i| {
             self.popped += 1;
             i
         })
@@ -117,7 +550,53 @@ impl MacroStream {
 
     /// Peek at the token at the given index in the stream.
     pub fn peek_at(&self, i: usize) -> Option<&Token> {
-        self.stream.get(i)
+        self.cursor.get(i)
+    }
+
+    /// Determine if the stream starts with the operator, as punctuation where every character but
+    /// the last is joint to the next. Like the Rust lexer, a longer operator in `OPERATORS` takes
+    /// precedence, so `<<` does not match the start of `<<=`.
+    pub fn starts_with_operator(&self, operator: &str) -> bool {
+        self.starts_with_joint(operator)
+            && !OPERATORS.iter().any(|longer| {
+                longer.len() > operator.len()
+                    && longer.starts_with(operator)
+                    && self.starts_with_joint(longer)
+            })
+    }
+
+    fn starts_with_joint(&self, operator: &str) -> bool {
+        let len = operator.chars().count();
+        operator.chars().enumerate().all(|(i, c)| {
+            matches!(
+                self.peek_at(i),
+                Some(Token::Punctuation { value, spacing, .. })
+                    if *value == c && (i + 1 == len || *spacing == Spacing::Joint)
+            )
+        })
+    }
+
+    /// Peek at the operator at the start of the stream, which is the longest operator in
+    /// `OPERATORS` that the stream starts with, or a single punctuation character otherwise.
+    pub fn peek_operator(&self) -> Option<String> {
+        match OPERATORS
+            .iter()
+            .filter(|operator| self.starts_with_joint(operator))
+            .max_by_key(|operator| operator.len())
+        {
+            Some(operator) => Some(operator.to_string()),
+            None => match self.peek() {
+                Some(Token::Punctuation { value, .. }) => Some(value.to_string()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Pop the operator at the start of the stream (see `MacroStream::peek_operator`), returning
+    /// its tokens.
+    pub fn pop_operator(&mut self) -> Option<Vec<Token>> {
+        let len = self.peek_operator()?.chars().count();
+        Some((0..len).filter_map(|_| self.pop()).collect())
     }
 
     /// Parse the stream into a type.
@@ -130,7 +609,7 @@ impl MacroStream {
 
     /// Determine if the stream is empty.
     pub fn is_empty(&self) -> bool {
-        self.stream.is_empty()
+        self.cursor.is_empty()
     }
 
     /// Pop a token from the stream, or return an error if the stream is empty.
@@ -154,49 +633,64 @@ impl MacroStream {
 
     /// Push a token to the front of the stream.
     pub fn push_front(&mut self, token: Token) {
-        self.stream.push_front(token)
+        self.cursor.push_front(token)
     }
 
     /// Push a token to the back of the stream.
     pub fn push_back(&mut self, token: Token) {
-        self.stream.push_back(token)
+        self.cursor.modify(|tokens| tokens.push(token))
     }
 
     /// Get the length of the stream.
     pub fn len(&self) -> usize {
-        self.stream.len()
+        self.cursor.len()
     }
 
-    /// Fork the stream (clone the stream and reset the popped count).
+    /// Fork the stream (copy the cursor and reset the popped count), this does not copy any
+    /// tokens.
     pub fn fork(&self) -> Self {
         Self {
-            stream: self.stream.clone(),
+            cursor: self.cursor.clone(),
             popped: 0,
+            path: self.path.clone(),
+            furthest: self.furthest.clone(),
+            recovered: None,
+            memo: self.memo.clone(),
+            alternative: self.alternative.clone(),
         }
     }
 
-    pub fn unfork(&mut self, other: Self) {
-        self.stream = other.stream;
+    pub fn unfork(&mut self, mut other: Self) {
+        self.cursor = std::mem::take(&mut other.cursor);
         self.popped = 0;
+        self.keep_recovered(other);
     }
 
     /// Pop a number of tokens from the stream.
     pub fn pop_many(&mut self, p: usize) {
-        for _ in 0..p {
-            self.pop().unwrap();
-        }
+        assert!(
+            p <= self.len(),
+            "cannot pop more tokens than are in the stream"
+        );
+        self.cursor.advance(p);
+        self.popped += p;
     }
 
-    pub fn append(&mut self, mut other: Self) {
-        self.stream.append(&mut other.stream)
+    pub fn append(&mut self, other: Self) {
+        if self.is_empty() {
+            self.cursor = other.cursor;
+        } else if !other.is_empty() {
+            self.cursor
+                .modify(|tokens| tokens.extend_from_slice(other.tokens()))
+        }
     }
 
     pub fn peek_back(&self) -> Option<&Token> {
-        self.stream.back()
+        self.tokens().last()
     }
 
     pub fn pop_back(&mut self) -> Option<Token> {
-        self.stream.pop_back()
+        self.cursor.pop_back().cloned()
     }
 }
 
@@ -212,9 +706,26 @@ impl Default for MacroStream {
     }
 }
 
+impl PartialEq for MacroStream {
+    fn eq(&self, other: &Self) -> bool {
+        self.tokens() == other.tokens()
+    }
+}
+
+impl Eq for MacroStream {}
+
+/// Only the tokens left in the stream are shown, not the rest of the buffer or the parsing state.
+impl std::fmt::Debug for MacroStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MacroStream")
+            .field("stream", &self.tokens())
+            .finish()
+    }
+}
+
 impl ToTokens for MacroStream {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        for i in &self.stream {
+        for i in self.tokens() {
             i.to_tokens(tokens);
         }
     }
@@ -223,7 +734,7 @@ impl ToTokens for MacroStream {
 impl ToString for MacroStream {
     fn to_string(&self) -> String {
         let mut s = String::new();
-        for i in &self.stream {
+        for i in self.tokens() {
             s.push_str(&i.to_string());
         }
         s
@@ -246,6 +757,30 @@ impl TryFrom<Match> for (Match,) {
     }
 }
 
+/// Run the expansion of a proc macro on its input, turning any error into `compile_error!`
+/// invocations (see `MacrosError::to_compile_error`) so that it is reported by the compiler.
+///
+/// # Example
+/// ```rs
+/// #[proc_macro]
+/// pub fn my_macro(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///     macros_core::expand(stream.into(), |mut stream| {
+///         let input = stream.parse::<MyInput>()?;
+///         Ok(quote! { ... })
+///     })
+///     .into()
+/// }
+/// ```
+pub fn expand(
+    input: TokenStream,
+    f: impl FnOnce(MacroStream) -> Result<TokenStream, MacrosError>,
+) -> TokenStream {
+    MacroStream::from_tokens(input)
+        .map_err(MacrosError::from)
+        .and_then(f)
+        .unwrap_or_else(|e| e.to_compile_error())
+}
+
 /// A shortcut for `proc_macro2::Span::call_site()`.
 #[inline(always)]
 pub fn call_site() -> Span {
@@ -255,5 +790,57 @@ pub fn call_site() -> Span {
 /// The trait for the output of a parser created by the `parser!` macro.
 pub trait ParserOutput {
     fn set_match(&mut self, k: &str, m: Match) -> Result<(), MacrosError>;
+
+    /// Set a parameter to the structured tree of its match. Only parameters with the type
+    /// `MatchTree` keep the tree, every other parameter is set to the flat `Match` of the tree
+    /// with `ParserOutput::set_match`.
+    fn set_tree(&mut self, k: &str, tree: MatchTree) -> Result<(), MacrosError> {
+        self.set_match(k, tree.into())
+    }
+
     fn name() -> &'static str;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn stream(input: &str) -> MacroStream {
+        MacroStream::from_tokens(TokenStream::from_str(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn operators() {
+        assert!(stream("=> a").starts_with_operator("=>"));
+        // the punctuation of an operator has to be joint
+        assert!(!stream("= > a").starts_with_operator("=>"));
+        assert!(stream("= > a").starts_with_operator("="));
+        // and the start of a longer operator is not a match
+        assert!(!stream("=> a").starts_with_operator("="));
+        assert!(!stream("<<= a").starts_with_operator("<<"));
+        assert!(stream("<<= a").starts_with_operator("<<="));
+        let patterns = Pattern::<DynamicOutput>::compile("=> {}ident").unwrap();
+        assert!(Pattern::execute(&patterns, &mut stream("=> a")).is_ok());
+        assert_eq!(
+            Pattern::execute(&patterns, &mut stream("= > a"))
+                .unwrap_err()
+                .to_string(),
+            "Expected `=>`"
+        );
+    }
+
+    #[test]
+    fn peek_operator() {
+        assert_eq!(stream("..= a").peek_operator().as_deref(), Some("..="));
+        assert_eq!(stream(":: a").peek_operator().as_deref(), Some("::"));
+        assert_eq!(stream(": : a").peek_operator().as_deref(), Some(":"));
+        // joint punctuation that is not an operator is a single character
+        assert_eq!(stream("#! a").peek_operator().as_deref(), Some("#"));
+        assert_eq!(stream("a").peek_operator(), None);
+        let mut arrow = stream("-> a");
+        assert_eq!(arrow.pop_operator().map(|tokens| tokens.len()), Some(2));
+        assert_eq!(arrow.len(), 1);
+    }
+}
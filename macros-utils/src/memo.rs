@@ -0,0 +1,52 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use crate::ParseError;
+
+/// The key of a memoized parse, which is the type that was parsed, the full position it was
+/// parsed at (see `MacroStream::path`), how many tokens were left in the stream, and whether
+/// failures were being tracked (since replaying a parse does not record its failures again).
+type Key = (TypeId, Vec<usize>, usize, bool);
+
+/// The result of a memoized parse, which is the value and how many tokens it consumed.
+type Entry = Result<(Rc<dyn Any>, usize), ParseError>;
+
+/// The results of memoized parses, shared between a `MacroStream` and its forks for the duration
+/// of the outermost `MacroStream::track`, see `MacroStream::memoize`.
+#[derive(Default)]
+pub(crate) struct Memo {
+    results: HashMap<Key, Entry>,
+}
+
+impl Memo {
+    /// Get the result of a parse, along with how many tokens it consumed if it succeeded.
+    pub(crate) fn get<T>(&self, key: &Key) -> Option<Result<(T, usize), ParseError>>
+    where
+        T: Clone + 'static,
+    {
+        Some(match self.results.get(key)? {
+            Ok((value, consumed)) => Ok((value.downcast_ref::<T>()?.clone(), *consumed)),
+            Err(e) => Err(e.clone()),
+        })
+    }
+
+    /// Record the result of a parse.
+    pub(crate) fn insert<T>(&mut self, key: Key, result: Result<(T, usize), ParseError>)
+    where
+        T: Clone + 'static,
+    {
+        let result = result.map(|(value, consumed)| (Rc::new(value) as Rc<dyn Any>, consumed));
+        self.results.insert(key, result);
+    }
+}
+
+impl std::fmt::Debug for Memo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Memo")
+            .field("results", &self.results.len())
+            .finish()
+    }
+}
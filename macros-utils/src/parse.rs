@@ -26,6 +26,14 @@ use crate::{LiteralKind, MacroStream, MacrosError, ParseError, ParseErrorKind, T
 /// }
 pub trait Parse: Sized {
     fn parse(input: &mut MacroStream) -> Result<Self, MacrosError>;
+
+    /// Parse a `Self` from the start of the stream, leaving whatever comes after it in the stream.
+    ///
+    /// This is the same as `Parse::parse` by default, it only differs for implementations that
+    /// reject trailing input like the parsers generated by `parser!` and `#[derive(Parse)]`.
+    fn parse_prefix(input: &mut MacroStream) -> Result<Self, MacrosError> {
+        Self::parse(input)
+    }
 }
 
 impl Parse for String {
@@ -146,3 +154,13 @@ impl Parse for MacroStream {
         Ok(input.clone())
     }
 }
+
+impl<T: Parse> Parse for Box<T> {
+    fn parse(input: &mut MacroStream) -> Result<Self, MacrosError> {
+        T::parse(input).map(Box::new)
+    }
+
+    fn parse_prefix(input: &mut MacroStream) -> Result<Self, MacrosError> {
+        T::parse_prefix(input).map(Box::new)
+    }
+}
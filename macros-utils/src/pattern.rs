@@ -1,11 +1,21 @@
 use std::{borrow::Cow, str::FromStr};
 
 use crate::{
-    call_site, Delimiter, MacroStream, MacrosError, Match, Parse, ParseError, ParseErrorKind,
-    ParserOutput, Spacing, Token,
+    call_site, Delimiter, LiteralKind, MacroStream, MacrosError, Match, MatchTree, Parse,
+    ParseError, ParseErrorKind, ParserOutput, Spacing, Token, TokenClass, Validator,
 };
-use proc_macro2::TokenStream;
-use proc_macro_error::{abort, abort_call_site};
+use proc_macro2::{Span, TokenStream};
+
+/// Whether a separated repetition (like `{...},*`) may end with a separator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trailing {
+    /// `{...},*`, a trailing separator is optional
+    Allow,
+    /// `{...},*!`, a trailing separator is an error
+    Forbid,
+    /// `{...},,*`, every repetition must be followed by a separator
+    Require,
+}
 
 #[doc(hidden)]
 pub struct ParserInput<T>
@@ -20,33 +30,130 @@ where
 /// The following are the various patterns that can be used:
 /// - {...}? indicates that the pattern is optional
 /// - {... : name : type}@ indicates that the match should be bound to the parameter `name` with the type `type`, the type can be any type that
+/// - {... : name : type = default}@ gives an optional parameter a default value instead of making it an `Option`
+/// - {... : name : type => map}@ converts the match of the parameter with a mapping function instead of parsing the type from its tokens
+/// - {... : name : MatchTree}@ sets the parameter to the structured tree of its match (see `MatchTree`)
+/// - {{ Type }# : name : Type}@ sets the parameter to the value parsed by the reference directly
 /// - {...}* indicates zero or more (non-greedy), meaning it will consume the stream until the next pattern matches
 /// - {...}** indicates zero or more (greedy), meaning it will consume the remainder of the stream
 /// - {...}+ indicates one or more (non-greedy), meaning it will consume the stream until the next pattern matches
 /// - {...}++ indicates one or more (greedy), meaning it will consume the remainder of the stream
+/// - {...}{n,m} indicates between n and m repetitions (non-greedy), {...}{{n,m}} is greedy
+/// - {...},* indicates zero or more repetitions separated by a separator (like a comma), {...},+ indicates one or more
+/// - {...}> indicates a positive lookahead, which matches without consuming anything if the pattern would match here
+/// - {...}! indicates a negative lookahead, which matches without consuming anything if the pattern would not match here
+/// - { Type }# indicates a reference to another parser or to any other type implementing `Parse`
 /// - {... | ... | ...}& indicates a choice
+/// - {}! indicates a cut, which commits to the current alternative once it is reached
+/// - {{Name: ... | Name: ...}& : name : enum Type}@ indicates a choice whose alternatives are the variants of a generated enum `Type`
+/// - {{...}* : name : struct Type}@ indicates a repetition whose body is parsed into a generated struct `Type`
 /// - ... indicates a token to match exactly
+/// - an operator made up of joint punctuation (like => or ::) is matched as a single unit
 /// - {}$ indicates an arbitrary token, if used in a zero or more or one or more then it will consume the stream until the next pattern matches
-/// - {...}= indicates a validation function, should be anything of type type `for<'a> fn(Cow<'a, T>, &Match) -> (Result<(), String>, Cow<'a, T>)` as it will be interpolated directly into the code expecting that type. Validation functions will receive the current output and the previous match, and should return the new output (allowing modification) and an optional error.
+/// - {}^ indicates the end of the input (or of the enclosing group)
+/// - {}rest indicates the rest of the input (or of the enclosing group)
+/// - {}ident, {}punct, {}group, {}lit, {}str, {}int, {}float, {}char, {}byte, and {}bytestr indicate an arbitrary token of that class
+/// - {... | ...}% indicates a pattern to recover from by skipping to one of the tokens after the `|`
+/// - {...}= indicates a validation function, which receives the current output and the previous match
 /// - {{...}} escapes the {} grouping
 /// - To escape any of the special endings, use ~whatever before the ending, to escape the tilde use ~~
+#[allow(clippy::large_enum_variant)]
 pub enum Pattern<T>
 where
     T: ToOwned<Owned = T> + ParserOutput,
 {
     Optional(Vec<Pattern<T>>),
-    Parameter(Vec<Pattern<T>>, String, MacroStream),
+    /// A parameter with its patterns, name, type, default value, and mapping function.
+    ///
+    /// The default value (like `{... : name : type = default}@`) is any expression of the type,
+    /// and is only allowed on a parameter that is optional (like in `{...}?` or a choice) and not
+    /// repeated. The parameter is then a plain `type` instead of an `Option`, which is set to the
+    /// default before any patterns are matched so validators see it too. With `#[derive(Parse)]`
+    /// the type can be left out, like `{... : name = default}@`.
+    ///
+    /// The mapping function (like `{... : name : type => map}@`) is a path to a function or a
+    /// closure without captures of type `fn(Match, Span) -> Result<type, MacrosError>`, which
+    /// receives the match and its span. It goes after the default value if there is one (like
+    /// `{... : name : type = default => map}@`).
+    ///
+    /// Default values and mapping functions are only used by generated parsers. A parameter with
+    /// the type `MatchTree` keeps the groups, the alternative of each choice, and the boundaries of
+    /// each repetition of its match instead of flattening them into a `Match`, and a parameter
+    /// bound to a reference (like `{{ Type }# : name : Type}@`) is set to the parsed value directly
+    /// instead of parsing its tokens again. A parameter whose type is `struct Type` is a nested
+    /// struct, see `Pattern::nested_struct`.
+    Parameter(
+        Vec<Pattern<T>>,
+        String,
+        MacroStream,
+        Option<MacroStream>,
+        Option<MacroStream>,
+    ),
     ZeroOrMore(Vec<Pattern<T>>, bool),
     OneOrMore(Vec<Pattern<T>>, bool),
+    /// A repetition with a minimum and an optional maximum number of repetitions, which is greedy
+    /// if the bool is true. `{...}{n}` is exactly n, `{...}{n,}` at least n, `{...}{,m}` at most
+    /// m, and `{...}{n,m}` between n and m. It is an error if the pattern would match again after
+    /// the maximum number of repetitions.
+    Repeat(Vec<Pattern<T>>, usize, Option<usize>, bool),
+    /// A repetition separated by a separator, which can be any punctuation or operator, and which
+    /// needs at least one repetition if the bool is true. A trailing separator is allowed unless
+    /// the repetition is followed by a joint `!` (like `{...},*!`, while `{...},* !` matches a `!`
+    /// token after the repetition), and is required if the separator is doubled (like
+    /// `{...},,*`). The match includes the separators.
+    Separated(Vec<Pattern<T>>, String, bool, Trailing),
+    /// A lookahead, which is negative if the bool is true. Like `{ {}$ }* { ( ... ) }>` for tokens
+    /// only if they are followed by a group, or `{ { ; }! {}$ }*` for any tokens that are not a
+    /// semicolon. The parameters inside of a lookahead are not set.
+    Lookahead(Vec<Pattern<T>>, bool),
+    /// A reference to another parser (like another rule of the same `parser!`) or to any other
+    /// type implementing `Parse`, which is parsed in place with `Parse::parse_prefix` and matches
+    /// the tokens it consumed. This allows grammars to be composed and to be recursive (but not
+    /// left recursive). References are only supported by `parser!` and `#[derive(Parse)]` since
+    /// they have to call into the type.
+    Reference(MacroStream),
     Choice(Vec<Vec<Pattern<T>>>),
+    /// A choice whose alternatives are named, in a parameter with an enum type like
+    /// `{{Name: ... | Name: ...}& : name : enum Type}@`. The parameter is an enum `Type` generated
+    /// by the parser with a variant for each alternative, containing the parameters of that
+    /// alternative.
+    NamedChoice(Vec<(String, Vec<Pattern<T>>)>),
+    /// A pattern to recover from and the tokens to skip to. If the patterns fail to match after
+    /// getting past their first token, the error is recorded and the stream is skipped up to the
+    /// next of the tokens (or to the end of the enclosing group if there are none, in which case
+    /// the `|` can be left out). Nothing is recovered from inside of an alternative of a choice so
+    /// that the other alternatives are tried, and all of the recorded errors are reported together
+    /// once parsing is done (see `MacroStream::recover`).
+    Recover(Vec<Pattern<T>>, Vec<Token>),
     Token(Token),
+    /// An operator made up of joint punctuation (like `=>` or `::`), which only matches the same
+    /// punctuation when it is joint and not the start of a longer operator (see
+    /// `MacroStream::starts_with_operator`).
+    Operator(String),
     Group(Delimiter, Vec<Pattern<T>>),
     Any,
-    #[allow(clippy::type_complexity)]
-    Validator(
-        Option<MacroStream>,
-        Option<for<'a> fn(Cow<'a, T>, &Match) -> (Result<(), String>, Cow<'a, T>)>,
-    ),
+    /// The end of the input or of the enclosing group, which is not required otherwise since
+    /// parsers only reject trailing input once all of the patterns have matched.
+    End,
+    /// The rest of the input or of the enclosing group, for parsers that intentionally stop early.
+    Rest,
+    /// A cut, which commits to the current alternative of the innermost choice (or to the current
+    /// repetition of the innermost repetition, or to an optional pattern matching) once it is
+    /// reached, so that if anything after it fails the error is reported instead of trying the
+    /// other alternatives. Like `{struct {}! {}ident {...}? ; | enum ...}&`. Cuts only apply
+    /// within the same parser (not across references).
+    Cut,
+    /// An arbitrary token of a class, like an identifier for `{}ident` or a string literal (raw or
+    /// not) for `{}str` (see `TokenClass`).
+    Class(TokenClass),
+    /// A validation function, which can be any function or closure (including a boxed one, so it
+    /// can be built from configuration) taking `(Cow<'a, T>, &Match)` and returning
+    /// `(R, Cow<'a, T>)`, as it will be interpolated directly into a call to `validate`. It
+    /// receives the current output and the previous match, and returns the new output (allowing
+    /// modification) and the result `R`. That is either a `Result<(), String>`, reported at the
+    /// span of the match, or a `Result<(), ParseError>` or `Result<(), MacrosError>` with its own
+    /// span (see `ValidatorResult`).
+    Validator(Option<MacroStream>, Option<Validator<T>>),
 }
 
 impl<T> ParserInput<T>
@@ -67,9 +174,9 @@ where
     T: ToOwned<Owned = T> + ParserOutput,
 {
     fn parse(stream: &mut MacroStream) -> Result<Self, MacrosError> {
-        Ok(Self {
-            patterns: stream_to_patterns(stream)?,
-        })
+        let patterns = stream_to_patterns(stream)?;
+        check_defaults(&patterns, false, false)?;
+        Ok(Self { patterns })
     }
 }
 
@@ -95,6 +202,261 @@ where
     Ok(patterns)
 }
 
+/// Check that only the parameters that are optional and not repeated have a default value (see
+/// `Pattern::defaults`), where `optional` and `variadic` are whether the enclosing patterns make
+/// `patterns` optional or repeated. Nested structs and the variants of named choices start over
+/// since their parameters belong to the generated types instead.
+fn check_defaults<T>(
+    patterns: &[Pattern<T>],
+    optional: bool,
+    variadic: bool,
+) -> Result<(), MacrosError>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    for pattern in patterns {
+        match pattern {
+            Pattern::Parameter(patterns, _, _, default, _) => {
+                let nested = pattern.nested_struct();
+                match default {
+                    Some(default) if variadic || !optional || nested.is_some() => {
+                        return Err(ParseError::new(
+                            default.peek().map(|t| t.span()).unwrap_or_else(call_site),
+                            ParseErrorKind::InvalidParameterDefault,
+                        )
+                        .into())
+                    },
+                    _ => {},
+                }
+                match nested {
+                    Some((_, _, body)) => check_defaults(body, false, false)?,
+                    None => check_defaults(patterns, optional, variadic)?,
+                }
+            },
+            Pattern::Optional(patterns) | Pattern::Recover(patterns, _) => {
+                check_defaults(patterns, true, variadic)?
+            },
+            Pattern::Choice(choices) => {
+                for choice in choices {
+                    check_defaults(choice, true, variadic)?;
+                }
+            },
+            Pattern::NamedChoice(choices) => {
+                for (_, choice) in choices {
+                    check_defaults(choice, false, false)?;
+                }
+            },
+            Pattern::ZeroOrMore(patterns, _)
+            | Pattern::OneOrMore(patterns, _)
+            | Pattern::Repeat(patterns, _, _, _)
+            | Pattern::Separated(patterns, _, _, _) => check_defaults(patterns, optional, true)?,
+            Pattern::Group(_, patterns) | Pattern::Lookahead(patterns, _) => {
+                check_defaults(patterns, optional, variadic)?
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// Parse the bounds of a bounded repetition (like the `n,m` in `{...}{n,m}`) into the minimum and
+/// maximum number of repetitions.
+fn repetition_bounds(
+    bounds: &MacroStream,
+    span: Span,
+) -> Result<(usize, Option<usize>), MacrosError> {
+    let count = |token: &Token| match token {
+        Token::Literal {
+            kind: LiteralKind::Integer,
+            value,
+            suffix,
+            ..
+        } if suffix.is_empty() => value.parse::<usize>().ok(),
+        _ => None,
+    };
+    let comma = |token: &Token| matches!(token, Token::Punctuation { value: ',', .. });
+    let (min, max) = match bounds.tokens() {
+        [n] => (count(n), count(n).map(Some)),
+        [n, c] if comma(c) => (count(n), Some(None)),
+        [c, m] if comma(c) => (Some(0), count(m).map(Some)),
+        [n, c, m] if comma(c) => (count(n), count(m).map(Some)),
+        _ => (None, None),
+    };
+    match (min, max) {
+        (Some(min), Some(max)) if !matches!(max, Some(max) if max < min) => Ok((min, max)),
+        _ => Err(ParseError::new(span, ParseErrorKind::InvalidRepetitionBounds).into()),
+    }
+}
+
+/// Convert the choice in a parameter with a type like `enum Name` into a `Pattern::NamedChoice`,
+/// stripping the `enum` keyword from the type.
+fn enum_parameter<T>(
+    mut patterns: Vec<Pattern<T>>,
+    mut type_: MacroStream,
+    span: Span,
+) -> Result<(Vec<Pattern<T>>, MacroStream), MacrosError>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    match type_.peek() {
+        Some(Token::Ident { name, .. }) if name == "enum" => {
+            type_.pop();
+        },
+        _ => return Ok((patterns, type_)),
+    }
+    match (type_.pop(), type_.peek()) {
+        (Some(ident @ Token::Ident { .. }), None) => type_.push_back(ident),
+        (Some(token), _) => {
+            return Err(ParseError::new(token.span(), ParseErrorKind::ExpectedEnumName).into())
+        },
+        (None, _) => return Err(ParseError::new(span, ParseErrorKind::ExpectedEnumName).into()),
+    }
+    let choices = match (patterns.pop(), patterns.is_empty()) {
+        (Some(Pattern::Choice(choices)), true) if !choices.is_empty() => choices,
+        _ => return Err(ParseError::new(span, ParseErrorKind::InvalidEnumParameter).into()),
+    };
+    let choices = choices
+        .into_iter()
+        .map(|mut choice| {
+            if let [Pattern::Token(Token::Ident { name, .. }), Pattern::Token(Token::Punctuation {
+                value: ':',
+                spacing: Spacing::Alone,
+                ..
+            }), _, ..] = choice.as_slice()
+            {
+                let name = name.clone();
+                choice.drain(..2);
+                return Ok((name, choice));
+            }
+            Err(ParseError::new(span, ParseErrorKind::ExpectedVariantName).into())
+        })
+        .collect::<Result<_, MacrosError>>()?;
+    Ok((vec![Pattern::NamedChoice(choices)], type_))
+}
+
+/// Split the type of a parameter (like `Type = default => map`) into the type, which is `Match` if
+/// it is left out, the default value, and the mapping function, which is everything after the
+/// first `=>` since a type never contains one.
+fn split_parameter_type(
+    type_: MacroStream,
+) -> Result<(MacroStream, Option<MacroStream>, Option<MacroStream>), MacrosError> {
+    let tokens = type_.tokens();
+    let arrow = tokens.windows(2).position(|tokens| {
+        matches!(
+            tokens,
+            [
+                Token::Punctuation {
+                    value: '=',
+                    spacing: Spacing::Joint,
+                    ..
+                },
+                Token::Punctuation { value: '>', .. }
+            ]
+        )
+    });
+    let (type_, map) = match arrow {
+        Some(i) if i + 2 == tokens.len() => {
+            return Err(
+                ParseError::new(tokens[i + 1].span(), ParseErrorKind::MissingParameterMap).into(),
+            )
+        },
+        Some(i) => (
+            MacroStream::from_vec(tokens[..i].to_vec()),
+            Some(MacroStream::from_vec(tokens[i + 2..].to_vec())),
+        ),
+        None => (type_, None),
+    };
+    let (type_, default) = split_default(type_)?;
+    let type_ = match type_.is_empty() {
+        true => {
+            MacroStream::from_tokens(TokenStream::from_str("macros_core::Match").unwrap()).unwrap()
+        },
+        false => type_,
+    };
+    Ok((type_, default, map))
+}
+
+/// Split the default value off of the type of a parameter (like `Type = default`) at the first `=`
+/// that is not inside of angle brackets (like in `impl Iterator<Item = u8>`).
+fn split_default(type_: MacroStream) -> Result<(MacroStream, Option<MacroStream>), MacrosError> {
+    let tokens = type_.tokens();
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|i| &tokens[i]);
+        match token {
+            Token::Punctuation { value: '<', .. } => depth += 1,
+            // the `>` of a `->` does not close an angle bracket
+            Token::Punctuation { value: '>', .. }
+                if !matches!(
+                    previous,
+                    Some(Token::Punctuation {
+                        value: '-',
+                        spacing: Spacing::Joint,
+                        ..
+                    })
+                ) =>
+            {
+                depth = depth.saturating_sub(1)
+            },
+            Token::Punctuation {
+                value: '=',
+                spacing: Spacing::Alone,
+                span,
+            } if depth == 0
+                && !matches!(
+                    previous,
+                    Some(Token::Punctuation {
+                        spacing: Spacing::Joint,
+                        ..
+                    })
+                ) =>
+            {
+                if i + 1 == tokens.len() {
+                    return Err(
+                        ParseError::new(*span, ParseErrorKind::MissingParameterDefault).into(),
+                    );
+                }
+                let default = MacroStream::from_vec(tokens[i + 1..].to_vec());
+                return Ok((MacroStream::from_vec(tokens[..i].to_vec()), Some(default)));
+            },
+            _ => {},
+        }
+    }
+    Ok((type_, None))
+}
+
+/// Check that a parameter with a type like `struct Name` contains exactly one repetition, see
+/// `Pattern::nested_struct`. Unlike `enum Name`, the `struct` keyword is kept in the type since
+/// that is what marks the parameter.
+fn struct_parameter<T>(
+    patterns: &[Pattern<T>],
+    type_: &MacroStream,
+    span: Span,
+) -> Result<(), MacrosError>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    match type_.tokens() {
+        [Token::Ident { name, .. }, rest @ ..] if name == "struct" => match rest {
+            [Token::Ident { .. }] => {},
+            [token, ..] => {
+                return Err(
+                    ParseError::new(token.span(), ParseErrorKind::ExpectedStructName).into(),
+                )
+            },
+            [] => return Err(ParseError::new(span, ParseErrorKind::ExpectedStructName).into()),
+        },
+        _ => return Ok(()),
+    }
+    match patterns {
+        [Pattern::ZeroOrMore(..)
+        | Pattern::OneOrMore(..)
+        | Pattern::Repeat(..)
+        | Pattern::Separated(..)] => Ok(()),
+        _ => Err(ParseError::new(span, ParseErrorKind::InvalidStructParameter).into()),
+    }
+}
+
 impl<T> Parse for Pattern<T>
 where
     T: ToOwned<Owned = T> + ParserOutput,
@@ -108,7 +470,7 @@ where
             Token::Group {
                 delimiter: Delimiter::Brace,
                 mut stream,
-                ..
+                span,
             } => {
                 let token = stream.pop_or_err();
                 let ending = input.peek();
@@ -122,40 +484,77 @@ where
                     },
                     Ok(token) => {
                         let t = match ending {
-                            Some(Token::Punctuation { value: '?', spacing: Spacing::Alone, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '?',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 stream.push_front(token);
                                 Self::Optional(stream_to_patterns(&mut stream)?)
                             },
-                            Some(Token::Punctuation { value: '*', spacing: Spacing::Alone, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '*',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 stream.push_front(token);
                                 Self::ZeroOrMore(stream_to_patterns(&mut stream)?, false)
                             },
-                            Some(Token::Punctuation { value: '*', spacing: Spacing::Joint, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '*',
+                                spacing: Spacing::Joint,
+                                ..
+                            }) => {
                                 stream.push_front(token);
-                                Self::ZeroOrMore(stream_to_patterns(&mut stream)?, match input.peek_at(1) {
-                                    Some(Token::Punctuation { value: '*', spacing: Spacing::Alone, .. }) => {
-                                        input.pop(); // pops the previous token off so that this one is popped off at the end of the match
-                                        true
+                                Self::ZeroOrMore(
+                                    stream_to_patterns(&mut stream)?,
+                                    match input.peek_at(1) {
+                                        Some(Token::Punctuation {
+                                            value: '*',
+                                            spacing: Spacing::Alone,
+                                            ..
+                                        }) => {
+                                            input.pop(); // pops the previous token off so that this one is popped off at the end of the match
+                                            true
+                                        },
+                                        _ => false,
                                     },
-                                    _ => false
-                                })
-
-                            }
-                            Some(Token::Punctuation { value: '+', spacing: Spacing::Alone, .. }) => {
+                                )
+                            },
+                            Some(Token::Punctuation {
+                                value: '+',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 stream.push_front(token);
                                 Self::OneOrMore(stream_to_patterns(&mut stream)?, false)
                             },
-                            Some(Token::Punctuation { value: '+', spacing: Spacing::Joint, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '+',
+                                spacing: Spacing::Joint,
+                                ..
+                            }) => {
                                 stream.push_front(token);
-                                Self::OneOrMore(stream_to_patterns(&mut stream)?, match input.peek_at(1) {
-                                    Some(Token::Punctuation { value: '+', spacing: Spacing::Alone, .. }) => {
-                                        input.pop(); // pops the previous token off so that this one is popped off at the end of the match
-                                        true
+                                Self::OneOrMore(
+                                    stream_to_patterns(&mut stream)?,
+                                    match input.peek_at(1) {
+                                        Some(Token::Punctuation {
+                                            value: '+',
+                                            spacing: Spacing::Alone,
+                                            ..
+                                        }) => {
+                                            input.pop(); // pops the previous token off so that this one is popped off at the end of the match
+                                            true
+                                        },
+                                        _ => false,
                                     },
-                                    _ => false
-                                })
-                            }
-                            Some(Token::Punctuation { value: '@', spacing: Spacing::Alone, .. }) => {
+                                )
+                            },
+                            Some(Token::Punctuation {
+                                value: '@',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 let mut span = token.span();
                                 stream.push_front(token);
                                 let mut patterns = vec![];
@@ -165,7 +564,11 @@ where
                                         span = token.span();
                                     }
                                     match token {
-                                        Some(Token::Punctuation { value: ':', spacing: Spacing::Alone, .. }) => {
+                                        Some(Token::Punctuation {
+                                            value: ':',
+                                            spacing: Spacing::Alone,
+                                            ..
+                                        }) => {
                                             stream.pop();
                                             break;
                                         },
@@ -173,37 +576,81 @@ where
                                     }
                                 }
                                 if stream.is_empty() {
-                                    abort!(span, "expected a pattern, a colon, then an ident, (like some_pattern_here:name), found end of input");
+                                    return Err(ParseError::new(
+                                        span,
+                                        ParseErrorKind::MissingParameterName,
+                                    )
+                                    .into());
                                 }
                                 if patterns.is_empty() {
-                                    abort!(span, "expected a pattern, a colon, then an ident, (like some_pattern_here:name), found no pattern");
+                                    return Err(ParseError::new(
+                                        span,
+                                        ParseErrorKind::MissingParameterPattern,
+                                    )
+                                    .into());
                                 }
                                 let token = stream.pop_or_err()?;
                                 match token {
                                     Token::Ident { name, .. } => {
-                                        let type_ = match stream.pop() {
-                                            Some(Token::Punctuation { value: ':', spacing: Spacing::Alone, span }) => {
+                                        let (type_, default, map) = match stream.pop() {
+                                            Some(Token::Punctuation {
+                                                value: ':',
+                                                spacing: Spacing::Alone,
+                                                span,
+                                            }) => {
                                                 if stream.is_empty() {
-                                                    abort!(span, "expected a type after the colon, found end of input");
+                                                    return Err(ParseError::new(
+                                                        span,
+                                                        ParseErrorKind::MissingParameterType,
+                                                    )
+                                                    .into());
                                                 }
-                                                stream
+                                                split_parameter_type(stream)?
+                                            },
+                                            // the type can be left out before a default or a mapping function
+                                            Some(token @ Token::Punctuation { value: '=', .. }) => {
+                                                stream.push_front(token);
+                                                split_parameter_type(stream)?
                                             },
-                                            Some(_) => abort!(span, "expected a colon after the identifier"),
-                                            None => MacroStream::from_tokens(TokenStream::from_str("macros_core::Match").unwrap()).unwrap(),
+                                            Some(token) => {
+                                                return Err(ParseError::new(
+                                                    token.span(),
+                                                    ParseErrorKind::ExpectedParameterTypeColon,
+                                                )
+                                                .into())
+                                            },
+                                            None => split_parameter_type(MacroStream::new())?,
                                         };
-                                        Self::Parameter(patterns, name, type_)
+                                        let (patterns, type_) =
+                                            enum_parameter(patterns, type_, span)?;
+                                        struct_parameter(&patterns, &type_, span)?;
+                                        Self::Parameter(patterns, name, type_, default, map)
+                                    },
+                                    _ => {
+                                        return Err(ParseError::new(
+                                            token.span(),
+                                            ParseErrorKind::InvalidParameterName,
+                                        )
+                                        .into())
                                     },
-                                    _ => abort!(token.span(), "expected an identifier"),
                                 }
                             },
-                            Some(Token::Punctuation { value: '&', spacing: Spacing::Alone, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '&',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 stream.push_front(token);
                                 let mut patterns = vec![];
                                 let mut current = vec![];
                                 while !stream.is_empty() {
                                     let token = stream.peek();
                                     match token {
-                                        Some(Token::Punctuation { value: '|', spacing: Spacing::Alone, .. }) => {
+                                        Some(Token::Punctuation {
+                                            value: '|',
+                                            spacing: Spacing::Alone,
+                                            ..
+                                        }) => {
                                             if !current.is_empty() {
                                                 patterns.push(current);
                                                 current = vec![];
@@ -219,26 +666,210 @@ where
                                 }
                                 Self::Choice(patterns)
                             },
-                            Some(Token::Punctuation { value: '$', spacing: Spacing::Alone, .. }) => {
-                                Self::Any
+                            Some(Token::Punctuation {
+                                value: '%',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
+                                let span = token.span();
+                                stream.push_front(token);
+                                let mut patterns = vec![];
+                                while let Some(token) = stream.peek() {
+                                    match token {
+                                        Token::Punctuation {
+                                            value: '|',
+                                            spacing: Spacing::Alone,
+                                            ..
+                                        } => {
+                                            stream.pop();
+                                            break;
+                                        },
+                                        _ => patterns.push(Pattern::parse(&mut stream)?),
+                                    }
+                                }
+                                if patterns.is_empty() {
+                                    return Err(ParseError::new(
+                                        span,
+                                        ParseErrorKind::MissingRecoverPattern,
+                                    )
+                                    .into());
+                                }
+                                let mut sync = vec![];
+                                while let Some(token) = stream.pop() {
+                                    sync.push(token);
+                                }
+                                Self::Recover(patterns, sync)
                             },
-                            Some(Token::Punctuation { value: '=', spacing: Spacing::Alone, .. }) => {
+                            Some(Token::Punctuation {
+                                value: '$',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => Self::Any,
+                            Some(Token::Punctuation {
+                                value: '=',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
                                 stream.push_front(token);
                                 Self::Validator(Some(stream), None)
                             },
+                            Some(Token::Group {
+                                delimiter: Delimiter::Brace,
+                                stream: bounds,
+                                span,
+                            }) => {
+                                let (min, max, greedy) = match bounds.tokens() {
+                                    [Token::Group {
+                                        delimiter: Delimiter::Brace,
+                                        stream: bounds,
+                                        ..
+                                    }] => {
+                                        let (min, max) = repetition_bounds(bounds, *span)?;
+                                        (min, max, true)
+                                    },
+                                    _ => {
+                                        let (min, max) = repetition_bounds(bounds, *span)?;
+                                        (min, max, false)
+                                    },
+                                };
+                                stream.push_front(token);
+                                Self::Repeat(stream_to_patterns(&mut stream)?, min, max, greedy)
+                            },
+                            Some(Token::Punctuation {
+                                value: '#',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
+                                stream.push_front(token);
+                                Self::Reference(stream)
+                            },
+                            Some(Token::Punctuation {
+                                value: '>',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
+                                stream.push_front(token);
+                                Self::Lookahead(stream_to_patterns(&mut stream)?, false)
+                            },
+                            Some(Token::Punctuation {
+                                value: '!',
+                                spacing: Spacing::Alone,
+                                ..
+                            }) => {
+                                stream.push_front(token);
+                                Self::Lookahead(stream_to_patterns(&mut stream)?, true)
+                            },
+                            Some(Token::Punctuation { span, .. }) => {
+                                let span = *span;
+                                let mut rest = input.fork();
+                                let separator = rest.peek_operator().unwrap();
+                                rest.pop_operator();
+                                let mut suffix = separator.chars().count();
+                                let doubled = rest.peek_operator().as_ref() == Some(&separator);
+                                if doubled {
+                                    rest.pop_operator();
+                                    suffix *= 2;
+                                }
+                                let (one, spacing) = match rest.pop() {
+                                    Some(Token::Punctuation {
+                                        value: '*',
+                                        spacing,
+                                        ..
+                                    }) => (false, spacing),
+                                    Some(Token::Punctuation {
+                                        value: '+',
+                                        spacing,
+                                        ..
+                                    }) => (true, spacing),
+                                    _ => {
+                                        return Err(ParseError::new(
+                                            span,
+                                            ParseErrorKind::InvalidPatternSuffix,
+                                        )
+                                        .into())
+                                    },
+                                };
+                                suffix += 1;
+                                let trailing = match rest.peek() {
+                                    _ if doubled => Trailing::Require,
+                                    // a `!` that is not joint with the `*` or `+` is a token to match after the repetition
+                                    Some(Token::Punctuation { value: '!', .. })
+                                        if spacing == Spacing::Joint =>
+                                    {
+                                        suffix += 1;
+                                        Trailing::Forbid
+                                    },
+                                    _ => Trailing::Allow,
+                                };
+                                // the last token of the suffix is popped off at the end of the match
+                                input.pop_many(suffix - 1);
+                                stream.push_front(token);
+                                Self::Separated(
+                                    stream_to_patterns(&mut stream)?,
+                                    separator,
+                                    one,
+                                    trailing,
+                                )
+                            },
                             _ => {
-                                abort!(token.span(), "expected one of ?*+=~@&$ after single braces")
+                                return Err(ParseError::new(
+                                    token.span(),
+                                    ParseErrorKind::InvalidPatternSuffix,
+                                )
+                                .into())
                             },
                         };
                         input.pop();
                         t
                     },
                     Err(_) => match ending {
-                        Some(Token::Punctuation { value: '$', spacing: Spacing::Alone, .. }) => {
+                        Some(Token::Punctuation {
+                            value: '$',
+                            spacing: Spacing::Alone,
+                            ..
+                        }) => {
                             input.pop();
                             Self::Any
                         },
-                        _ => abort_call_site!("found empty group, expected either an any pattern (like {}$) after the braces or something in the braces"),
+                        Some(Token::Punctuation {
+                            value: '^',
+                            spacing: Spacing::Alone,
+                            ..
+                        }) => {
+                            input.pop();
+                            Self::End
+                        },
+                        Some(Token::Punctuation {
+                            value: '!',
+                            spacing: Spacing::Alone,
+                            ..
+                        }) => {
+                            input.pop();
+                            Self::Cut
+                        },
+                        Some(Token::Ident { name, .. }) if name == "rest" => {
+                            input.pop();
+                            Self::Rest
+                        },
+                        Some(Token::Ident { name, span }) => {
+                            let class = match TokenClass::from_name(name) {
+                                Some(class) => class,
+                                None => {
+                                    return Err(ParseError::new(
+                                        *span,
+                                        ParseErrorKind::UnknownTokenClass(name.clone()),
+                                    )
+                                    .into())
+                                },
+                            };
+                            input.pop();
+                            Self::Class(class)
+                        },
+                        _ => {
+                            return Err(
+                                ParseError::new(span, ParseErrorKind::EmptyPatternGroup).into()
+                            )
+                        },
                     },
                 }
             },
@@ -249,10 +880,15 @@ where
                 })?;
                 match next {
                     next @ Token::Punctuation {
-                        value: '?' | '*' | '+' | '=' | '~' | '@' | '&' | '$',
+                        value:
+                            '?' | '*' | '+' | '=' | '~' | '@' | '&' | '%' | '$' | '!' | '>' | '#' | '^',
                         ..
                     } => Self::Token(next),
-                    _ => abort!(next.span(), "expected one of ?*+=~@&$ after tilde"),
+                    _ => {
+                        return Err(
+                            ParseError::new(next.span(), ParseErrorKind::InvalidEscape).into()
+                        )
+                    },
                 }
             },
             Token::Group {
@@ -260,30 +896,222 @@ where
                 mut stream,
                 ..
             } => Self::Group(delimiter, stream_to_patterns(&mut stream)?),
+            Token::Punctuation {
+                value,
+                spacing: Spacing::Joint,
+                ..
+            } if matches!(input.peek(), Some(Token::Punctuation { value, .. }) if *value != '~') =>
+            {
+                let mut operator = value.to_string();
+                while let Some(Token::Punctuation { value, spacing, .. }) = input.peek() {
+                    if *value == '~' {
+                        break;
+                    }
+                    operator.push(*value);
+                    let joint = *spacing == Spacing::Joint;
+                    input.pop();
+                    if !joint {
+                        break;
+                    }
+                }
+                Self::Operator(operator)
+            },
             token => Self::Token(token),
         })
     }
 }
 
+impl<T> FromStr for Pattern<T>
+where
+    T: ToOwned<Owned = T> + ParserOutput,
+{
+    type Err = MacrosError;
+
+    /// Parse a single pattern from a string, see `Pattern::compile` for a sequence of patterns.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stream = tokenize(s)?;
+        let pattern = Self::parse(&mut stream)?;
+        stream.expect_end()?;
+        Ok(pattern)
+    }
+}
+
+fn tokenize(s: &str) -> Result<MacroStream, MacrosError> {
+    let stream = TokenStream::from_str(s)
+        .map_err(|e| ParseError::new(e.span(), ParseErrorKind::User(e.to_string())))?;
+    Ok(MacroStream::from_tokens(stream)?)
+}
+
 impl<T> Pattern<T>
 where
     T: ToOwned<Owned = T> + ParserOutput,
 {
+    /// Compile a sequence of patterns from a string at runtime, using the same syntax as the
+    /// `parser!` macro. Validators are not supported since there is no code to run for them, so
+    /// they are ignored when matching. References to other parsers (like `{ Type }#`) are not
+    /// supported either, so they are an `UnsupportedReference` error.
+    ///
+    /// # Example
+    /// ```rs
+    /// use macros_core::{DynamicOutput, Pattern};
+    ///
+    /// let patterns = Pattern::<DynamicOutput>::compile("fn { {}$ : name }@ { ( { {}$ }* ) }?")?;
+    /// ```
+    pub fn compile(s: &str) -> Result<Vec<Self>, MacrosError> {
+        let patterns = stream_to_patterns(&mut tokenize(s)?)?;
+        check_defaults(&patterns, false, false)?;
+        // a reference can never match, so it is rejected here rather than being a failure that a
+        // choice or a repetition would silently skip
+        match patterns.iter().find_map(Self::reference) {
+            Some(type_) => Err(ParseError::new(
+                type_.peek().map(|t| t.span()).unwrap_or_else(call_site),
+                ParseErrorKind::UnsupportedReference(type_.to_string()),
+            )
+            .into()),
+            None => Ok(patterns),
+        }
+    }
+
+    /// Find the first reference to another parser (like `{ Type }#`) in the pattern.
+    fn reference(&self) -> Option<&MacroStream> {
+        match self {
+            Self::Reference(type_) => Some(type_),
+            Self::Optional(patterns)
+            | Self::Parameter(patterns, _, _, _, _)
+            | Self::ZeroOrMore(patterns, _)
+            | Self::OneOrMore(patterns, _)
+            | Self::Repeat(patterns, _, _, _)
+            | Self::Separated(patterns, _, _, _)
+            | Self::Lookahead(patterns, _)
+            | Self::Recover(patterns, _)
+            | Self::Group(_, patterns) => patterns.iter().find_map(Self::reference),
+            Self::Choice(choices) => choices.iter().flatten().find_map(Self::reference),
+            Self::NamedChoice(choices) => choices
+                .iter()
+                .flat_map(|(_, choice)| choice)
+                .find_map(Self::reference),
+            _ => None,
+        }
+    }
+
+    /// Match a sequence of patterns against the stream, starting from the default output, and
+    /// return the output if they match. Like the parsers generated by `parser!`, it is an error if
+    /// there are tokens left in the stream after the patterns match (see `Pattern::Rest`).
+    pub fn execute(patterns: &[Self], stream: &mut MacroStream) -> Result<T, MacrosError>
+    where
+        T: Default,
+    {
+        stream.track(|stream| {
+            let (res, output) = Self::match_patterns(Cow::Owned(T::default()), patterns, stream);
+            res?;
+            stream.expect_end()?;
+            Ok(output.into_owned())
+        })
+    }
+
+    /// Get the name, the type (without the `struct` keyword), and the body of the repetition of a
+    /// parameter with a type like `struct Name`, whose parameters belong to the generated struct
+    /// instead of to the output. The parameter is a `Vec` with an instance of the struct for each
+    /// repetition (of any kind), so that parameters from the same repetition stay together, while
+    /// patterns compiled at runtime set the parameters of the body directly instead.
+    pub fn nested_struct(&self) -> Option<(&str, MacroStream, &[Pattern<T>])> {
+        let Self::Parameter(patterns, name, type_, _, _) = self else {
+            return None;
+        };
+        let [Token::Ident { name: keyword, .. }, type_ @ Token::Ident { .. }] = type_.tokens()
+        else {
+            return None;
+        };
+        let body = match patterns.as_slice() {
+            [Self::ZeroOrMore(body, _)
+            | Self::OneOrMore(body, _)
+            | Self::Repeat(body, _, _, _)
+            | Self::Separated(body, _, _, _)] => body,
+            _ => return None,
+        };
+        let type_ = MacroStream::from_vec(vec![type_.clone()]);
+        (keyword == "struct").then_some((name.as_str(), type_, body.as_slice()))
+    }
+
+    /// Get the parameters with a default value (like `{... : name : Type = default}@`) along with
+    /// the default, which are not optional since they are set to the default when they do not
+    /// match. Only optional parameters that are not repeated can have a default.
+    pub fn defaults(&self) -> Vec<(String, MacroStream)> {
+        if self.nested_struct().is_some() {
+            return vec![];
+        }
+        match self {
+            Self::Group(_, patterns) | Self::Optional(patterns) | Self::Recover(patterns, _) => {
+                patterns.iter().flat_map(|i| i.defaults()).collect()
+            },
+            Self::Choice(choices) => choices
+                .iter()
+                .flatten()
+                .flat_map(|i| i.defaults())
+                .collect(),
+            Self::Parameter(patterns, name, _, default, _) => {
+                let mut defaults = patterns
+                    .iter()
+                    .flat_map(|i| i.defaults())
+                    .collect::<Vec<_>>();
+                if let Some(default) = default {
+                    defaults.push((name.clone(), default.clone()));
+                }
+                defaults
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Get the parameters with a mapping function (like `{... : name : Type => map}@`) along with
+    /// the function, following `Pattern::params`.
+    pub fn maps(&self) -> Vec<(String, MacroStream)> {
+        if self.nested_struct().is_some() {
+            return vec![];
+        }
+        match self {
+            Self::Group(_, patterns)
+            | Self::Optional(patterns)
+            | Self::Recover(patterns, _)
+            | Self::ZeroOrMore(patterns, _)
+            | Self::OneOrMore(patterns, _)
+            | Self::Repeat(patterns, _, _, _)
+            | Self::Separated(patterns, _, _, _) => {
+                patterns.iter().flat_map(|i| i.maps()).collect()
+            },
+            Self::Choice(choices) => choices.iter().flatten().flat_map(|i| i.maps()).collect(),
+            Self::Parameter(patterns, name, _, _, map) => {
+                let mut maps = patterns.iter().flat_map(|i| i.maps()).collect::<Vec<_>>();
+                if let Some(map) = map {
+                    maps.push((name.clone(), map.clone()));
+                }
+                maps
+            },
+            _ => vec![],
+        }
+    }
+
     pub fn params(&self) -> Vec<(String, bool, bool, MacroStream)> {
         let mut params = vec![];
+        if let Some((name, type_, _)) = self.nested_struct() {
+            params.push((name.to_string(), false, true, type_));
+            return params;
+        }
+        // a parameter with a default is never missing, even when it is in an optional pattern
+        let defaults = self.defaults();
+        let optional = |name: &String| !defaults.iter().any(|(default, _)| default == name);
         match self {
             Self::Group(_, patterns) => {
                 for i in patterns {
                     params.extend(i.params());
                 }
             },
-            Self::Optional(patterns) => {
+            // a pattern that was recovered from does not set its parameters
+            Self::Optional(patterns) | Self::Recover(patterns, _) => {
                 for i in patterns {
-                    params.extend(
-                        i.params()
-                            .into_iter()
-                            .map(|(name, _, variadic, type_)| (name, true, variadic, type_)),
-                    );
+                    params.extend(i.params().into_iter().map(|(name, _, variadic, type_)| {
+                        (name.clone(), optional(&name), variadic, type_)
+                    }));
                 }
             },
             Self::ZeroOrMore(patterns, _) => {
@@ -295,7 +1123,9 @@ where
                     );
                 }
             },
-            Self::OneOrMore(patterns, _) => {
+            Self::OneOrMore(patterns, _)
+            | Self::Repeat(patterns, _, _, _)
+            | Self::Separated(patterns, _, _, _) => {
                 for i in patterns {
                     params.extend(
                         i.params()
@@ -307,15 +1137,15 @@ where
             Self::Choice(patterns) => {
                 for i in patterns {
                     for j in i {
-                        params.extend(
-                            j.params()
-                                .into_iter()
-                                .map(|(name, _, variadic, type_)| (name, true, variadic, type_)),
-                        );
+                        params.extend(j.params().into_iter().map(|(name, _, variadic, type_)| {
+                            (name.clone(), optional(&name), variadic, type_)
+                        }));
                     }
                 }
             },
-            Self::Parameter(patterns, name, type_) => {
+            // the parameters of a named choice belong to the variants of its enum instead
+            Self::NamedChoice(_) => {},
+            Self::Parameter(patterns, name, type_, _, _) => {
                 for i in patterns {
                     params.extend(i.params());
                 }
@@ -328,116 +1158,162 @@ where
 
     pub fn match_pattern<'a>(
         &self,
-        mut output: Cow<'a, T>,
+        output: Cow<'a, T>,
         next: Option<&Pattern<T>>,
         next2: Option<&Pattern<T>>,
         stream: &mut MacroStream,
     ) -> (Result<Match, MacrosError>, Cow<'a, T>) {
+        let (res, output) = self.match_pattern_tree(output, next, next2, stream);
+        (res.map(Match::from), output)
+    }
+
+    /// Match a single pattern like `Pattern::match_pattern`, but return the structured tree of the
+    /// match instead of flattening it.
+    pub fn match_pattern_tree<'a>(
+        &self,
+        mut output: Cow<'a, T>,
+        next: Option<&Pattern<T>>,
+        next2: Option<&Pattern<T>>,
+        stream: &mut MacroStream,
+    ) -> (Result<MatchTree, MacrosError>, Cow<'a, T>) {
         let match_next = match next {
             Some(Pattern::Validator(_, _)) => next2,
             _ => next,
         };
         let res = match self {
             Self::Any => (
-                stream
-                    .pop_or_err()
-                    .map(Match::One)
-                    .map_err(MacrosError::Parse),
+                match stream.pop() {
+                    Some(token) => Ok(MatchTree::Token(token)),
+                    None => Err(MacrosError::Parse(stream.expected("any token"))),
+                },
                 output,
             ),
-            Self::Choice(choices) => {
-                'choice: for choice in choices {
-                    let mut fork = stream.fork();
-                    let (res, o) = Self::match_patterns(output, choice, &mut fork);
-                    if res.is_err() {
-                        output = o;
-                        continue 'choice;
-                    }
-                    stream.unfork(fork);
-                    return (res, o);
+            Self::Operator(operator) => (
+                match stream.starts_with_operator(operator) {
+                    true => Ok(MatchTree::Sequence(
+                        operator
+                            .chars()
+                            .map(|_| MatchTree::Token(stream.pop().unwrap()))
+                            .collect(),
+                    )),
+                    false => Err(MacrosError::Parse(
+                        stream.expected(&format!("`{}`", operator)),
+                    )),
+                },
+                output,
+            ),
+            Self::Reference(type_) => (
+                Err(MacrosError::Parse(ParseError::call_site(
+                    ParseErrorKind::UnsupportedReference(type_.to_string()),
+                ))),
+                output,
+            ),
+            Self::End => (
+                match stream.peek() {
+                    Some(_) => Err(MacrosError::Parse(stream.expected("the end of input"))),
+                    None => Ok(MatchTree::None),
+                },
+                output,
+            ),
+            Self::Rest => {
+                let mut matches = vec![];
+                while let Some(token) = stream.pop() {
+                    matches.push(MatchTree::Token(token));
                 }
                 (
-                    Err(MacrosError::Parse(ParseError::new(
-                        stream.peek().map(|t| t.span()).unwrap_or_else(call_site),
-                        ParseErrorKind::NoMatchingChoice,
-                    ))),
+                    if matches.is_empty() {
+                        Ok(MatchTree::None)
+                    } else {
+                        Ok(MatchTree::Sequence(matches))
+                    },
                     output,
                 )
             },
+            Self::Cut => {
+                stream.cut();
+                (Ok(MatchTree::None), output)
+            },
+            Self::Class(class) => (
+                match stream.peek() {
+                    Some(token) if class.matches(token) => {
+                        Ok(MatchTree::Token(stream.pop().unwrap()))
+                    },
+                    _ => Err(MacrosError::Parse(stream.expected(&class.describe()))),
+                },
+                output,
+            ),
+            Self::Choice(choices) => Self::match_choices(output, choices.iter(), stream),
+            Self::NamedChoice(choices) => {
+                Self::match_choices(output, choices.iter().map(|(_, choice)| choice), stream)
+            },
             Self::Group(delimiter, patterns) => {
-                let token = match stream.pop_or_err().map_err(MacrosError::Parse) {
-                    Ok(token) => token,
-                    Err(e) => return (Err(e), output),
+                let (mut fork, span) = match stream.peek() {
+                    Some(Token::Group {
+                        delimiter: d,
+                        stream: s,
+                        span,
+                    }) if d == delimiter => (stream.enter(s), *span),
+                    _ => {
+                        return (
+                            Err(MacrosError::Parse(
+                                stream.expected(&delimiter.describe_start()),
+                            )),
+                            output,
+                        )
+                    },
                 };
-                if let Token::Group {
-                    delimiter: d,
-                    stream: s,
-                    ..
-                } = &token
-                {
-                    if d == delimiter {
-                        let mut fork = s.fork();
-                        let (res, o) = Self::match_patterns(output, patterns, &mut fork);
-                        if !fork.is_empty() {
-                            return (
-                                Err(MacrosError::Parse(ParseError::new(
-                                    stream.peek().map(|t| t.span()).unwrap_or_else(call_site),
-                                    ParseErrorKind::InputTooLong,
-                                ))),
-                                o,
-                            );
-                        }
-                        return (res, o);
-                    }
+                let (res, o) = Self::match_sequence(output, patterns, &mut fork);
+                if res.is_ok() && !fork.is_empty() {
+                    return (
+                        Err(MacrosError::Parse(fork.expected(&delimiter.describe_end()))),
+                        o,
+                    );
                 }
+                stream.leave(fork);
                 (
-                    Err(MacrosError::Parse(ParseError::new(
-                        token.span(),
-                        ParseErrorKind::ExpectedGroup(*delimiter),
-                    ))),
-                    output,
+                    res.map(|m| MatchTree::Group(*delimiter, span, Box::new(m))),
+                    o,
                 )
             },
             Self::OneOrMore(patterns, greedy) => {
                 let mut matches = vec![];
                 loop {
-                    let mut fork = stream.fork();
-                    match Self::match_patterns(output, patterns, &mut fork) {
+                    let mut fork = stream.alternative();
+                    match Self::match_sequence(output, patterns, &mut fork) {
                         (Ok(m), o) => {
+                            let progress = fork.len() < stream.len();
                             stream.unfork(fork);
                             matches.push(m);
                             output = o;
+                            if !progress {
+                                break;
+                            }
                         },
                         (Err(e), o) => {
                             output = o;
-                            if matches.is_empty() {
+                            if matches.is_empty() || fork.is_cut() {
                                 return (Err(e), output);
                             }
                             break;
                         },
                     }
-                    let mut fork = stream.fork();
-                    match match_next {
-                        Some(next) if !greedy && !matches.is_empty() => {
-                            match next.match_pattern(output, None, None, &mut fork) {
-                                (Ok(_), o) => {
-                                    output = o;
-                                    break;
-                                },
-                                (_, o) => output = o,
-                            }
-                        },
-                        _ => {},
+                    // the following pattern is matched against a copy of the output so that its
+                    // parameters are not set by the check
+                    if let Some(next) = match_next.filter(|_| !greedy) {
+                        let mut fork = stream.lookahead();
+                        let (res, _) = next.match_pattern(output.clone(), None, None, &mut fork);
+                        if res.is_ok() {
+                            break;
+                        }
                     }
                 }
                 (
                     if matches.is_empty() {
-                        Err(MacrosError::Parse(ParseError::new(
-                            stream.peek().map(|t| t.span()).unwrap_or_else(call_site),
-                            ParseErrorKind::ExpectedRepetition,
-                        )))
+                        Err(MacrosError::Parse(
+                            stream.error(ParseErrorKind::ExpectedRepetition),
+                        ))
                     } else {
-                        Ok(Match::Many(matches))
+                        Ok(MatchTree::Repetition(matches))
                     },
                     output,
                 )
@@ -445,70 +1321,186 @@ where
             Self::ZeroOrMore(patterns, greedy) => {
                 let mut matches = vec![];
                 loop {
-                    let mut fork = stream.fork();
-                    match Self::match_patterns(output, patterns, &mut fork) {
+                    let mut fork = stream.alternative();
+                    match Self::match_sequence(output, patterns, &mut fork) {
                         (Ok(m), o) => {
+                            let progress = fork.len() < stream.len();
                             stream.unfork(fork);
                             matches.push(m);
                             output = o;
+                            if !progress {
+                                break;
+                            }
                         },
-                        (_, o) => {
+                        (Err(e), o) => {
                             output = o;
+                            if fork.is_cut() {
+                                return (Err(e), output);
+                            }
                             break;
                         },
                     }
-                    let mut fork = stream.fork();
-                    match match_next {
-                        Some(next) if !greedy => {
-                            match next.match_pattern(output.clone(), None, None, &mut fork) {
-                                (Ok(_), o) => {
-                                    output = o;
-                                    break;
-                                },
-                                (_, o) => output = o,
+                    if let Some(next) = match_next.filter(|_| !greedy) {
+                        let mut fork = stream.lookahead();
+                        let (res, _) = next.match_pattern(output.clone(), None, None, &mut fork);
+                        if res.is_ok() {
+                            break;
+                        }
+                    }
+                }
+                (Ok(MatchTree::Repetition(matches)), output)
+            },
+            Self::Repeat(patterns, min, max, greedy) => {
+                let next_matches = |output: &Cow<'a, T>, stream: &MacroStream| match match_next {
+                    Some(next) if !greedy => next
+                        .match_pattern(output.clone(), None, None, &mut stream.lookahead())
+                        .0
+                        .is_ok(),
+                    _ => false,
+                };
+                let mut matches = vec![];
+                loop {
+                    if Some(matches.len()) == *max
+                        || matches.len() >= *min && next_matches(&output, stream)
+                    {
+                        break;
+                    }
+                    let mut fork = stream.alternative();
+                    match Self::match_sequence(output, patterns, &mut fork) {
+                        (Ok(m), o) => {
+                            let progress = fork.len() < stream.len();
+                            stream.unfork(fork);
+                            matches.push(m);
+                            output = o;
+                            if !progress {
+                                break;
                             }
                         },
-                        _ => {},
+                        (Err(e), o) => {
+                            output = o;
+                            if matches.len() < *min || fork.is_cut() {
+                                return (Err(e), output);
+                            }
+                            break;
+                        },
                     }
                 }
-                (
-                    if matches.is_empty() {
-                        Ok(Match::None)
-                    } else {
-                        Ok(Match::Many(matches))
-                    },
-                    output,
-                )
+                // report the first surplus repetition rather than letting whatever comes next fail
+                // to match it
+                if let Some(max) = max.filter(|max| matches.len() == *max) {
+                    let mut fork = stream.lookahead();
+                    if !next_matches(&output, stream)
+                        && Self::match_sequence(output.clone(), patterns, &mut fork)
+                            .0
+                            .is_ok()
+                        && fork.len() < stream.len()
+                    {
+                        return (
+                            Err(MacrosError::Parse(ParseError::new(
+                                stream.peek().map(|t| t.span()).unwrap_or_else(call_site),
+                                ParseErrorKind::TooManyRepetitions(max),
+                            ))),
+                            output,
+                        );
+                    }
+                }
+                (Ok(MatchTree::Repetition(matches)), output)
+            },
+            Self::Separated(patterns, separator, one, trailing) => {
+                let mut matches = vec![];
+                let mut separators = vec![];
+                loop {
+                    let mut fork = stream.alternative();
+                    match Self::match_sequence(output, patterns, &mut fork) {
+                        (Ok(m), o) => {
+                            stream.unfork(fork);
+                            matches.push(m);
+                            output = o;
+                        },
+                        (Err(e), o) => {
+                            output = o;
+                            // either nothing was matched or the last separator was trailing
+                            if matches.is_empty() && *one
+                                || !matches.is_empty() && *trailing == Trailing::Forbid
+                                || fork.is_cut()
+                            {
+                                return (Err(e), output);
+                            }
+                            break;
+                        },
+                    }
+                    if !stream.starts_with_operator(separator) {
+                        // the separator is recorded as expected even when it is optional
+                        let e = stream.expected(&format!("`{}`", separator));
+                        if *trailing == Trailing::Require {
+                            return (Err(MacrosError::Parse(e)), output);
+                        }
+                        break;
+                    }
+                    separators.push(MatchTree::Sequence(
+                        separator
+                            .chars()
+                            .map(|_| MatchTree::Token(stream.pop().unwrap()))
+                            .collect(),
+                    ));
+                }
+                (Ok(MatchTree::Separated(matches, separators)), output)
+            },
+            // the patterns are matched against a copy of the output so that their parameters are not
+            // set
+            Self::Lookahead(patterns, false) => {
+                let mut fork = stream.alternative();
+                match Self::match_sequence(output.clone(), patterns, &mut fork).0 {
+                    Ok(_) => (Ok(MatchTree::None), output),
+                    Err(e) => (Err(e), output),
+                }
+            },
+            Self::Lookahead(patterns, true) => {
+                let mut fork = stream.lookahead();
+                match Self::match_sequence(output.clone(), patterns, &mut fork).0 {
+                    Ok(_) => (Err(MacrosError::Parse(stream.unexpected())), output),
+                    Err(_) => (Ok(MatchTree::None), output),
+                }
             },
             Self::Optional(patterns) => {
-                let mut fork = stream.fork();
-                match Self::match_patterns(output.clone(), patterns, &mut fork) {
-                    r @ (Ok(_), _) => {
+                let mut fork = stream.alternative();
+                match Self::match_sequence(output.clone(), patterns, &mut fork) {
+                    (Ok(m), o) => {
                         stream.unfork(fork);
-                        r
+                        (Ok(MatchTree::Optional(Some(Box::new(m)))), o)
                     },
-                    (_, o) => (Ok(Match::None), o),
+                    r @ (Err(_), _) if fork.is_cut() => r,
+                    (_, o) => (Ok(MatchTree::Optional(None)), o),
                 }
             },
+            Self::Recover(patterns, sync) => {
+                let mut output = Some(output);
+                let res = stream.recover(
+                    |stream| {
+                        let (res, o) =
+                            Self::match_sequence(output.take().unwrap(), patterns, stream);
+                        output = Some(o);
+                        res
+                    },
+                    |token| sync.contains(token),
+                );
+                (res, output.unwrap())
+            },
             Self::Token(token) => (
-                match stream.pop_or_err().map_err(MacrosError::Parse) {
-                    Ok(t) if t == *token => Ok(Match::One(t)),
-                    Ok(t) => Err(MacrosError::Parse(ParseError::new(
-                        t.span(),
-                        ParseErrorKind::Expected(token.clone(), t),
-                    ))),
-                    Err(e) => Err(e),
+                match stream.peek() {
+                    Some(t) if t == token => Ok(MatchTree::Token(stream.pop().unwrap())),
+                    _ => Err(MacrosError::Parse(stream.expected(&token.describe()))),
                 },
                 output,
             ),
-            Self::Parameter(patterns, name, _) => {
+            Self::Parameter(patterns, name, _, _, _) => {
                 let mut fork = stream.fork();
-                let (res, mut o) = Self::match_patterns(output, patterns, &mut fork);
+                let (res, mut o) = Self::match_sequence(output, patterns, &mut fork);
                 match res {
                     Ok(m) => {
                         stream.unfork(fork);
-                        if let Err(e) = o.to_mut().set_match(name, m.clone()) {
-                            (Err(e), o)
+                        if let Err(e) = o.to_mut().set_tree(name, m.clone()) {
+                            (Err(e.forget_position()), o)
                         } else {
                             (Ok(m), o)
                         }
@@ -521,45 +1513,237 @@ where
             ),
         };
         match (next, res) {
-            (Some(Pattern::Validator(_, Some(f))), (Ok(m), output)) => match f(output, &m) {
-                (Ok(_), o) => (Ok(m), o),
-                (Err(e), o) => (
-                    Err(MacrosError::Parse(ParseError::new(
-                        stream.peek().map(|t| t.span()).unwrap_or_else(call_site),
-                        ParseErrorKind::ValidatorFailed(e),
-                    ))),
-                    o,
-                ),
+            (Some(Pattern::Validator(_, Some(f))), (Ok(m), output)) => {
+                let (res, o) = f(output, &Match::from(m.clone()));
+                (res.map(|_| m), o)
             },
             (_, m) => m,
         }
     }
 
-    pub fn match_patterns<'b, 'a: 'b>(
+    fn match_choices<'b, 'a: 'b>(
         mut output: Cow<'a, T>,
+        choices: impl Iterator<Item = &'b Vec<Pattern<T>>>,
+        stream: &mut MacroStream,
+    ) -> (Result<MatchTree, MacrosError>, Cow<'a, T>)
+    where
+        T: 'b,
+    {
+        for (i, choice) in choices.enumerate() {
+            let mut fork = stream.choice();
+            let (res, o) = Self::match_sequence(output, choice, &mut fork);
+            if res.is_err() && fork.is_cut() {
+                return (res, o);
+            }
+            if res.is_err() {
+                output = o;
+                continue;
+            }
+            stream.unfork(fork);
+            return (res.map(|m| MatchTree::Choice(i, Box::new(m))), o);
+        }
+        (
+            Err(MacrosError::Parse(
+                stream.error(ParseErrorKind::NoMatchingChoice),
+            )),
+            output,
+        )
+    }
+
+    /// Match a sequence of patterns against the stream, reporting the furthest failure (see
+    /// `MacroStream::track`) if they do not match.
+    pub fn match_patterns<'b, 'a: 'b>(
+        output: Cow<'a, T>,
         patterns: &'b [Pattern<T>],
         stream: &mut MacroStream,
     ) -> (Result<Match, MacrosError>, Cow<'a, T>) {
+        let (res, output) = Self::match_patterns_tree(output, patterns, stream);
+        (res.map(Match::from), output)
+    }
+
+    /// Match a sequence of patterns like `Pattern::match_patterns`, but return the structured tree
+    /// of the match instead of flattening it.
+    pub fn match_patterns_tree<'b, 'a: 'b>(
+        output: Cow<'a, T>,
+        patterns: &'b [Pattern<T>],
+        stream: &mut MacroStream,
+    ) -> (Result<MatchTree, MacrosError>, Cow<'a, T>) {
+        let mut output = Some(output);
+        let res = stream.track(|stream| {
+            let (res, o) = Self::match_sequence(output.take().unwrap(), patterns, stream);
+            output = Some(o);
+            res
+        });
+        (res, output.unwrap())
+    }
+
+    fn match_sequence<'b, 'a: 'b>(
+        mut output: Cow<'a, T>,
+        patterns: &'b [Pattern<T>],
+        stream: &mut MacroStream,
+    ) -> (Result<MatchTree, MacrosError>, Cow<'a, T>) {
         let mut matches = vec![];
         for (i, pattern) in patterns.iter().enumerate() {
             if let Pattern::Validator(_, _) = pattern {
                 continue;
             }
-            match pattern.match_pattern(output, patterns.get(i + 1), patterns.get(i + 2), stream) {
-                (Ok(m @ Match::One(_)), o) => {
+            match pattern.match_pattern_tree(
+                output,
+                patterns.get(i + 1),
+                patterns.get(i + 2),
+                stream,
+            ) {
+                (Ok(m), o) => {
                     matches.push(m);
                     output = o;
                 },
-                (Ok(Match::None), o) => output = o,
-                (Ok(Match::Many(m)), o) => {
-                    matches.extend(m);
-                    output = o;
-                },
                 e => return e,
             }
         }
-        (Ok(Match::Many(matches)), output)
+        (Ok(MatchTree::Sequence(matches)), output)
     }
 }
 
-unsafe impl<T> Sync for Pattern<T> where T: ToOwned<Owned = T> + ParserOutput {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DynamicOutput;
+
+    fn stream(input: &str) -> MacroStream {
+        MacroStream::from_tokens(TokenStream::from_str(input).unwrap()).unwrap()
+    }
+
+    fn execute(patterns: &str, input: &str) -> Result<DynamicOutput, MacrosError> {
+        Pattern::execute(&Pattern::compile(patterns)?, &mut stream(input))
+    }
+
+    fn matches(output: &DynamicOutput, name: &str) -> Vec<MacroStream> {
+        output
+            .get_all(name)
+            .iter()
+            .map(|m| MacroStream::from(m.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn compile() {
+        let function = "fn { {}ident : name }@ ( { { {}ident : args }@ },* )";
+        let output = execute(function, "fn f(a, b)").unwrap();
+        assert_eq!(matches(&output, "name"), vec![stream("f")]);
+        assert_eq!(matches(&output, "args"), vec![stream("a"), stream("b")]);
+        assert!(output.get_tree("name").is_some());
+        let mut names = output.names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["args", "name"]);
+        assert!(!execute(function, "fn f()").unwrap().contains("args"));
+        assert_eq!(
+            execute(function, "fn f(a) ;").unwrap_err().to_string(),
+            "Input is too long"
+        );
+        // there is no code to run for a validator, so it always passes
+        assert!(execute("{ {}ident : name }@ { check }=", "a").is_ok());
+        assert!(execute("{ Type }# ;", ";")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Found a reference to Type"));
+    }
+
+    #[test]
+    fn from_str() {
+        let pattern = "{ {}$ : name }@".parse::<Pattern<DynamicOutput>>().unwrap();
+        assert!(matches!(pattern, Pattern::Parameter(_, name, ..) if name == "name"));
+        assert_eq!(
+            "a b"
+                .parse::<Pattern<DynamicOutput>>()
+                .err()
+                .map(|e| e.to_string()),
+            Some("Input is too long".to_string())
+        );
+    }
+
+    fn error(patterns: &str) -> ParseErrorKind {
+        match Pattern::<DynamicOutput>::compile(patterns) {
+            Err(MacrosError::Parse(error)) => error.error,
+            Err(error) => panic!("expected a parse error, found {:?}", error),
+            Ok(_) => panic!("expected `{}` to be malformed", patterns),
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(
+            error("{ a }"),
+            ParseErrorKind::InvalidPatternSuffix
+        ));
+        assert!(matches!(
+            error("{ a },"),
+            ParseErrorKind::InvalidPatternSuffix
+        ));
+        assert!(matches!(error("~ a"), ParseErrorKind::InvalidEscape));
+        assert!(matches!(error("{ }"), ParseErrorKind::EmptyPatternGroup));
+        assert!(matches!(
+            error("{ a }@"),
+            ParseErrorKind::MissingParameterName
+        ));
+        assert!(matches!(
+            error("{ : name }@"),
+            ParseErrorKind::MissingParameterPattern
+        ));
+        assert!(matches!(
+            error("{ a : 1 }@"),
+            ParseErrorKind::InvalidParameterName
+        ));
+        assert!(matches!(
+            error("{ a : name ; }@"),
+            ParseErrorKind::ExpectedParameterTypeColon
+        ));
+        assert!(matches!(
+            error("{ a : name : }@"),
+            ParseErrorKind::MissingParameterType
+        ));
+        assert!(matches!(
+            error("{ { A: a }& : name : enum }@"),
+            ParseErrorKind::ExpectedEnumName
+        ));
+        assert!(matches!(
+            error("{ a : name : enum Kind }@"),
+            ParseErrorKind::InvalidEnumParameter
+        ));
+        assert!(matches!(
+            error("{ { a | b }& : name : enum Kind }@"),
+            ParseErrorKind::ExpectedVariantName
+        ));
+        assert!(matches!(
+            error("{ | ; }%"),
+            ParseErrorKind::MissingRecoverPattern
+        ));
+    }
+
+    #[test]
+    fn defaults() {
+        assert!(Pattern::<DynamicOutput>::compile("{ { {}int : size : u8 = 4 }@ }?").is_ok());
+        assert!(Pattern::<DynamicOutput>::compile("{ { {}int : size : u8 = 4 }@ | x }&").is_ok());
+        // parameters that always match or are always a `Vec` cannot have a default
+        for patterns in [
+            "{ {}int : size : u8 = 4 }@",
+            "{ { {}int : sizes : u8 = 4 }@ }*",
+            "{ { { {}int : sizes : u8 = 4 }@ }? }*",
+            "{ { { {}int : size : u8 = 4 }@ }* : entries : struct Entry }@",
+            "{ { A: { {}int : size : u8 = 4 }@ | B: b }& : kind : enum Kind }@",
+        ] {
+            assert!(matches!(
+                error(patterns),
+                ParseErrorKind::InvalidParameterDefault
+            ));
+        }
+        // while nested structs and named choices have their own optional parameters
+        assert!(Pattern::<DynamicOutput>::compile(
+            "{ { { { {}int : size : u8 = 4 }@ }? }* : entries : struct Entry }@"
+        )
+        .is_ok());
+        assert!(Pattern::<DynamicOutput>::compile(
+            "{ { A: { { {}int : size : u8 = 4 }@ }? | B: b }& : kind : enum Kind }@"
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{MacroStream, MacrosError, ParseErrorKind, Token};
+
+/// How a binary operator groups with itself, like `a - b - c` being `(a - b) - c`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+    /// `a == b == c` is an error.
+    None,
+}
+
+/// A precedence table for parsing expressions made up of atoms and prefix, binary, and postfix
+/// operators, building a user-defined AST with a callback for each operator.
+///
+/// Operators are either punctuation (matched like `MacroStream::starts_with_operator`) or
+/// keywords (like `and`). An operator with a higher binding power binds more tightly, so with
+/// `*` at 2 and `+` at 1, `a + b * c` is `a + (b * c)`.
+///
+/// Expressions usually nest (like in parentheses), so the atom parser will usually parse the
+/// type the table is for recursively, either directly or with a reference (`{ Type }#`) from a
+/// `parser!`. A `parser!` rule can also be a precedence table, see `parser!`.
+///
+/// The atom parser and the callbacks can be any functions or closures, so they can capture their
+/// configuration.
+///
+/// # Example
+/// ```rs
+/// use macros_core::{Associativity, MacroStream, MacrosError, Parse, Precedence};
+///
+/// enum Expr {
+///     Num(i64),
+///     Add(Box<Expr>, Box<Expr>),
+///     Mul(Box<Expr>, Box<Expr>),
+///     Neg(Box<Expr>),
+/// }
+///
+/// impl Parse for Expr {
+///     fn parse(input: &mut MacroStream) -> Result<Self, MacrosError> {
+///         Precedence::new(|input| Ok(Expr::Num(input.parse()?)))
+///             .infix("+", 1, Associativity::Left, |a, b| Expr::Add(a.into(), b.into()))
+///             .infix("*", 2, Associativity::Left, |a, b| Expr::Mul(a.into(), b.into()))
+///             .prefix("-", 3, |a| Expr::Neg(a.into()))
+///             .parse(input)
+///     }
+/// }
+/// ```
+pub struct Precedence<E> {
+    atom: Atom<E>,
+    prefix: Vec<(String, u8, Unary<E>)>,
+    infix: Vec<(String, u8, Associativity, Binary<E>)>,
+    postfix: Vec<(String, u8, Unary<E>)>,
+}
+
+type Atom<E> = Box<dyn Fn(&mut MacroStream) -> Result<E, MacrosError>>;
+type Unary<E> = Box<dyn Fn(E) -> E>;
+type Binary<E> = Box<dyn Fn(E, E) -> E>;
+
+impl<E> Precedence<E> {
+    /// Create a table with no operators, using `atom` to parse the operands.
+    pub fn new(atom: impl Fn(&mut MacroStream) -> Result<E, MacrosError> + 'static) -> Self {
+        Self {
+            atom: Box::new(atom),
+            prefix: vec![],
+            infix: vec![],
+            postfix: vec![],
+        }
+    }
+
+    /// Add a prefix operator, like `-a` or `!a`.
+    pub fn prefix(mut self, operator: &str, power: u8, build: impl Fn(E) -> E + 'static) -> Self {
+        self.prefix
+            .push((operator.to_string(), power, Box::new(build)));
+        self
+    }
+
+    /// Add a binary operator, like `a + b`.
+    pub fn infix(
+        mut self,
+        operator: &str,
+        power: u8,
+        associativity: Associativity,
+        build: impl Fn(E, E) -> E + 'static,
+    ) -> Self {
+        let build = Box::new(build);
+        self.infix
+            .push((operator.to_string(), power, associativity, build));
+        self
+    }
+
+    /// Add a postfix operator, like `a?`.
+    pub fn postfix(mut self, operator: &str, power: u8, build: impl Fn(E) -> E + 'static) -> Self {
+        self.postfix
+            .push((operator.to_string(), power, Box::new(build)));
+        self
+    }
+
+    /// Parse an expression from the start of the stream, leaving whatever comes after it in the
+    /// stream.
+    pub fn parse(&self, stream: &mut MacroStream) -> Result<E, MacrosError> {
+        self.parse_expr(stream, 0)
+    }
+
+    /// Parse an expression whose operators have a left binding power of at least `min`. Binding
+    /// powers are doubled so that left associative operators can bind their right operand one
+    /// more tightly than themselves.
+    fn parse_expr(&self, stream: &mut MacroStream, min: u16) -> Result<E, MacrosError> {
+        let mut lhs = match find(&self.prefix, |(op, ..)| op, stream) {
+            Some((op, power, build)) => {
+                pop(stream, op);
+                build(self.parse_expr(stream, u16::from(*power) * 2)?)
+            },
+            None => (self.atom)(stream)?,
+        };
+        let mut non_associative: Option<(&String, u16)> = None;
+        loop {
+            if let Some((op, power, build)) = find(&self.postfix, |(op, ..)| op, stream) {
+                if u16::from(*power) * 2 < min {
+                    break;
+                }
+                pop(stream, op);
+                lhs = build(lhs);
+                continue;
+            }
+            let Some((op, power, associativity, build)) = find(&self.infix, |(op, ..)| op, stream)
+            else {
+                // the operators are only expected where parsing got stuck, otherwise the end of
+                // every expression would add them to an error that is reported there
+                if stream.is_furthest() {
+                    let postfix = self.postfix.iter().map(|(op, ..)| op);
+                    for op in postfix.chain(self.infix.iter().map(|(op, ..)| op)) {
+                        stream.expected(&format!("`{}`", op));
+                    }
+                }
+                break;
+            };
+            let power = u16::from(*power) * 2;
+            if power < min {
+                break;
+            }
+            if let Some((previous, p)) = non_associative {
+                if p == power {
+                    return Err(stream
+                        .error(ParseErrorKind::NonAssociativeOperator(previous.to_string()))
+                        .into());
+                }
+            }
+            pop(stream, op);
+            let rhs = match associativity {
+                Associativity::Right => self.parse_expr(stream, power)?,
+                Associativity::Left | Associativity::None => self.parse_expr(stream, power + 1)?,
+            };
+            lhs = build(lhs, rhs);
+            non_associative = (*associativity == Associativity::None).then_some((op, power));
+        }
+        Ok(lhs)
+    }
+}
+
+/// Find the longest of the operators that the stream starts with, so that `**` is not matched as
+/// `*` when both are in the table.
+fn find<'a, T>(
+    operators: &'a [T],
+    operator: impl Fn(&T) -> &String,
+    stream: &MacroStream,
+) -> Option<&'a T> {
+    operators
+        .iter()
+        .filter(|o| starts_with(stream, operator(o)))
+        .max_by_key(|o| operator(o).len())
+}
+
+/// Determine if the operator is a keyword (like `and`) rather than punctuation.
+fn is_keyword(operator: &str) -> bool {
+    operator.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn starts_with(stream: &MacroStream, operator: &str) -> bool {
+    match is_keyword(operator) {
+        true => matches!(stream.peek(), Some(Token::Ident { name, .. }) if name == operator),
+        false => stream.starts_with_operator(operator),
+    }
+}
+
+fn pop(stream: &mut MacroStream, operator: &str) {
+    stream.pop_many(match is_keyword(operator) {
+        true => 1,
+        false => operator.chars().count(),
+    });
+}
@@ -1,7 +1,10 @@
 use proc_macro2::{Spacing, Span};
 use quote::quote;
 
-use crate::{tokens::LiteralKind, Delimiter, MacroStream, ParserOutput, Pattern, Token};
+use crate::{
+    pattern::Trailing, tokens::LiteralKind, Delimiter, MacroStream, ParserOutput, Pattern, Token,
+    TokenClass,
+};
 
 /// The representation of an item as tokens to recreate it.
 pub trait Repr {
@@ -89,7 +92,7 @@ impl Repr for Delimiter {
 
 impl Repr for MacroStream {
     fn repr(&self, name: &str) -> MacroStream {
-        let tokens = self.stream.iter().map(|token| token.repr(name));
+        let tokens = self.tokens().iter().map(|token| token.repr(name));
         quote! {
             macros_core::MacroStream::from_vec(vec![
                 #(#tokens),*
@@ -124,6 +127,33 @@ impl Repr for LiteralKind {
     }
 }
 
+impl Repr for Trailing {
+    fn repr(&self, _: &str) -> MacroStream {
+        match self {
+            Self::Allow => quote! { macros_core::Trailing::Allow },
+            Self::Forbid => quote! { macros_core::Trailing::Forbid },
+            Self::Require => quote! { macros_core::Trailing::Require },
+        }
+        .into()
+    }
+}
+
+impl Repr for TokenClass {
+    fn repr(&self, name: &str) -> MacroStream {
+        match self {
+            Self::Ident => quote! { macros_core::TokenClass::Ident },
+            Self::Punctuation => quote! { macros_core::TokenClass::Punctuation },
+            Self::Group => quote! { macros_core::TokenClass::Group },
+            Self::Literal => quote! { macros_core::TokenClass::Literal },
+            Self::Kind(kind) => {
+                let kind = kind.repr(name);
+                quote! { macros_core::TokenClass::Kind(#kind) }
+            },
+        }
+        .into()
+    }
+}
+
 impl Repr for Spacing {
     fn repr(&self, _: &str) -> MacroStream {
         match self {
@@ -145,12 +175,42 @@ where
         };
         match self {
             Self::Any => quote! { macros_core::Pattern::<#type_name>::Any },
+            Self::End => quote! { macros_core::Pattern::<#type_name>::End },
+            Self::Rest => quote! { macros_core::Pattern::<#type_name>::Rest },
+            Self::Cut => quote! { macros_core::Pattern::<#type_name>::Cut },
+            Self::Operator(operator) => quote! {
+                macros_core::Pattern::<#type_name>::Operator(#operator.to_string())
+            },
+            Self::Class(class) => {
+                let class = class.repr(name);
+                quote! {
+                    macros_core::Pattern::<#type_name>::Class(#class)
+                }
+            },
             Self::Choice(patterns) => {
                 let patterns = patterns.repr(name);
                 quote! {
                     macros_core::Pattern::<#type_name>::Choice(#patterns)
                 }
             },
+            Self::NamedChoice(choices) => {
+                let choices = choices.iter().map(|(variant, patterns)| {
+                    let patterns = patterns.repr(name);
+                    quote! {
+                        (#variant.to_string(), #patterns)
+                    }
+                });
+                quote! {
+                    macros_core::Pattern::<#type_name>::NamedChoice(vec![#(#choices),*])
+                }
+            },
+            Self::Recover(pattern, sync) => {
+                let patterns = pattern.repr(name);
+                let sync = sync.repr(name);
+                quote! {
+                    macros_core::Pattern::<#type_name>::Recover(#patterns, #sync)
+                }
+            },
             Self::Group(delimiter, pattern) => {
                 let delimiter = delimiter.repr(name);
                 let patterns = pattern.repr(name);
@@ -170,11 +230,13 @@ where
                     macros_core::Pattern::<#type_name>::Optional(#pattern)
                 }
             },
-            Self::Parameter(pattern, parameter, type_) => {
+            Self::Parameter(pattern, parameter, type_, default, map) => {
                 let pattern = pattern.repr(name);
                 let type_ = type_.repr(name);
+                let default = default.repr(name);
+                let map = map.repr(name);
                 quote! {
-                    macros_core::Pattern::<#type_name>::Parameter(#pattern, #parameter.into(), #type_)
+                    macros_core::Pattern::<#type_name>::Parameter(#pattern, #parameter.into(), #type_, #default, #map)
                 }
             },
             Self::Token(token) => {
@@ -185,7 +247,7 @@ where
             },
             Self::Validator(stream, _) => {
                 let func = match stream {
-                    Some(s) => quote! { Some({#s}) },
+                    Some(s) => quote! { Some(macros_core::validator({#s})) },
                     None => quote! { None },
                 };
                 quote! {
@@ -198,6 +260,35 @@ where
                     macros_core::Pattern::<#type_name>::ZeroOrMore(#pattern, #greedy)
                 }
             },
+            Self::Reference(type_) => {
+                let type_ = type_.repr(name);
+                quote! {
+                    macros_core::Pattern::<#type_name>::Reference(#type_)
+                }
+            },
+            Self::Lookahead(pattern, negative) => {
+                let pattern = pattern.repr(name);
+                quote! {
+                    macros_core::Pattern::<#type_name>::Lookahead(#pattern, #negative)
+                }
+            },
+            Self::Separated(pattern, separator, one, trailing) => {
+                let pattern = pattern.repr(name);
+                let trailing = trailing.repr(name);
+                quote! {
+                    macros_core::Pattern::<#type_name>::Separated(#pattern, #separator.to_string(), #one, #trailing)
+                }
+            },
+            Self::Repeat(pattern, min, max, greedy) => {
+                let pattern = pattern.repr(name);
+                let max = match max {
+                    Some(max) => quote!(Some(#max)),
+                    None => quote!(None),
+                };
+                quote! {
+                    macros_core::Pattern::<#type_name>::Repeat(#pattern, #min, #max, #greedy)
+                }
+            },
         }
         .into()
     }
@@ -49,6 +49,22 @@ impl Delimiter {
             Self::None => None,
         }
     }
+
+    /// Describe the start of a group with this delimiter, for use in error messages.
+    pub fn describe_start(&self) -> String {
+        match self.start_char() {
+            Some(c) => format!("`{}`", c),
+            None => "a group".to_string(),
+        }
+    }
+
+    /// Describe the end of a group with this delimiter, for use in error messages.
+    pub fn describe_end(&self) -> String {
+        match self.end_char() {
+            Some(c) => format!("`{}`", c),
+            None => "the end of the group".to_string(),
+        }
+    }
 }
 
 impl Display for Delimiter {
@@ -246,6 +262,89 @@ impl LiteralKind {
             span: Span::call_site(),
         }
     }
+
+    /// The kind ignoring whether it is raw.
+    fn unraw(&self) -> Self {
+        match self {
+            Self::StrRaw(_) => Self::Str,
+            Self::ByteStrRaw(_) => Self::ByteStr,
+            kind => kind.clone(),
+        }
+    }
+}
+
+/// The operators made up of more than one punctuation character in Rust, which are matched as a
+/// single unit by patterns and `MacroStream::peek_operator`.
+pub const OPERATORS: &[&str] = &[
+    "&&", "||", "<<", ">>", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=", "<<=", ">>=", "==",
+    "!=", ">=", "<=", "=>", "->", "<-", "::", "..", "...", "..=",
+];
+
+/// A class of tokens matched by a pattern like `{}ident`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TokenClass {
+    /// `{}ident`, any identifier (or keyword)
+    Ident,
+    /// `{}punct`, any punctuation character
+    Punctuation,
+    /// `{}group`, any group regardless of its delimiter
+    Group,
+    /// `{}lit`, any literal
+    Literal,
+    /// A literal of a specific kind, `{}str`, `{}int`, `{}float`, `{}char`, `{}byte`, or
+    /// `{}bytestr`. Raw strings are matched by `LiteralKind::Str` and `LiteralKind::ByteStr`, and
+    /// the number of `#` symbols of the kind is ignored.
+    Kind(LiteralKind),
+}
+
+impl TokenClass {
+    /// Get the class with the given name, as it is written after `{}` in a pattern.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ident" => Self::Ident,
+            "punct" => Self::Punctuation,
+            "group" => Self::Group,
+            "lit" => Self::Literal,
+            "str" => Self::Kind(LiteralKind::Str),
+            "int" => Self::Kind(LiteralKind::Integer),
+            "float" => Self::Kind(LiteralKind::Float),
+            "char" => Self::Kind(LiteralKind::Char),
+            "byte" => Self::Kind(LiteralKind::Byte),
+            "bytestr" => Self::Kind(LiteralKind::ByteStr),
+            _ => return None,
+        })
+    }
+
+    /// Determine if the token belongs to this class.
+    pub fn matches(&self, token: &Token) -> bool {
+        match (self, token) {
+            (Self::Ident, Token::Ident { .. }) => true,
+            (Self::Punctuation, Token::Punctuation { .. }) => true,
+            (Self::Group, Token::Group { .. }) => true,
+            (Self::Literal, Token::Literal { .. }) => true,
+            (Self::Kind(expected), Token::Literal { kind, .. }) => expected.unraw() == kind.unraw(),
+            _ => false,
+        }
+    }
+
+    /// Describe the class, for use in error messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Ident => "an identifier",
+            Self::Punctuation => "a punctuation character",
+            Self::Group => "a group",
+            Self::Literal => "a literal",
+            Self::Kind(LiteralKind::Byte) => "a byte literal",
+            Self::Kind(LiteralKind::Char) => "a character literal",
+            Self::Kind(LiteralKind::Integer) => "an integer literal",
+            Self::Kind(LiteralKind::Float) => "a float literal",
+            Self::Kind(LiteralKind::Str | LiteralKind::StrRaw(_)) => "a string literal",
+            Self::Kind(LiteralKind::ByteStr | LiteralKind::ByteStrRaw(_)) => {
+                "a byte string literal"
+            },
+        }
+        .to_string()
+    }
 }
 
 impl Token {
@@ -407,6 +506,11 @@ impl Token {
         })
     }
 
+    /// Describe the token, for use in error messages.
+    pub fn describe(&self) -> String {
+        format!("`{}`", ToTokens::to_token_stream(self))
+    }
+
     pub fn ident(&self) -> Option<&str> {
         if let Token::Ident { name, .. } = self {
             Some(name)
@@ -620,3 +724,63 @@ impl ToTokens for Token {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DynamicOutput, Pattern};
+
+    /// Get the indices of the tokens of the input that belong to the class with the given name.
+    fn matching(input: &str, name: &str) -> Vec<usize> {
+        let class = TokenClass::from_name(name).unwrap();
+        let stream = MacroStream::from_tokens(TokenStream::from_str(input).unwrap()).unwrap();
+        let tokens = stream.tokens();
+        (0..tokens.len())
+            .filter(|&i| class.matches(&tokens[i]))
+            .collect()
+    }
+
+    #[test]
+    fn classes() {
+        let input = r#"a + (b) "s" r"raw" 1 1.5 'c' b'b' b"bytes" br"raw""#;
+        assert_eq!(matching(input, "ident"), vec![0]);
+        assert_eq!(matching(input, "punct"), vec![1]);
+        assert_eq!(matching(input, "group"), vec![2]);
+        assert_eq!(matching(input, "lit"), (3..=10).collect::<Vec<_>>());
+        assert_eq!(matching(input, "str"), vec![3, 4]);
+        assert_eq!(matching(input, "int"), vec![5]);
+        assert_eq!(matching(input, "float"), vec![6]);
+        assert_eq!(matching(input, "char"), vec![7]);
+        assert_eq!(matching(input, "byte"), vec![8]);
+        assert_eq!(matching(input, "bytestr"), vec![9, 10]);
+        assert!(TokenClass::from_name("number").is_none());
+    }
+
+    #[test]
+    fn describe() {
+        let described = [
+            ("ident", "an identifier"),
+            ("punct", "a punctuation character"),
+            ("group", "a group"),
+            ("lit", "a literal"),
+            ("str", "a string literal"),
+            ("int", "an integer literal"),
+            ("float", "a float literal"),
+            ("char", "a character literal"),
+            ("byte", "a byte literal"),
+            ("bytestr", "a byte string literal"),
+        ];
+        for (name, description) in described {
+            assert_eq!(TokenClass::from_name(name).unwrap().describe(), description);
+        }
+        // the description is what a pattern of the class expects
+        let patterns = Pattern::<DynamicOutput>::compile("{}int").unwrap();
+        let mut stream = MacroStream::from_tokens(TokenStream::from_str("a").unwrap()).unwrap();
+        assert_eq!(
+            Pattern::execute(&patterns, &mut stream)
+                .unwrap_err()
+                .to_string(),
+            "Expected an integer literal"
+        );
+    }
+}
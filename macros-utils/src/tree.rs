@@ -0,0 +1,83 @@
+use proc_macro2::Span;
+
+use crate::{Delimiter, MacrosError, Match, Token};
+
+/// A match of a `Pattern` that keeps the structure of the patterns that matched, unlike `Match`
+/// which flattens everything but repetitions into a list of tokens.
+///
+/// A parameter with the type `macros_core::MatchTree` is set to the tree of its patterns (see
+/// `ParserOutput::set_tree`), and a tree can be converted to the flat `Match` that the same
+/// patterns would have produced with `Match::from`.
+#[derive(Clone, Debug, Default)]
+pub enum MatchTree {
+    /// A single token, matched exactly, by a token class, or by `{}$`.
+    Token(Token),
+    /// A sequence of patterns, with an entry for each pattern in the sequence (other than
+    /// validators), or the tokens consumed by an operator, `{}rest`, or a reference.
+    Sequence(Vec<MatchTree>),
+    /// A repetition (like `{...}*` or `{...}{n,m}`), with an entry for each repetition.
+    Repetition(Vec<MatchTree>),
+    /// A separated repetition (like `{...},*`), with an entry for each repetition and for each
+    /// separator after one.
+    Separated(Vec<MatchTree>, Vec<MatchTree>),
+    /// The alternative of a choice that matched, with its index in the choice.
+    Choice(usize, Box<MatchTree>),
+    /// A group, with its delimiter and span.
+    Group(Delimiter, Span, Box<MatchTree>),
+    /// An optional pattern, which is `None` if it did not match.
+    Optional(Option<Box<MatchTree>>),
+    /// A pattern that does not consume anything, like a lookahead or `{}^`, or one that was
+    /// recovered from.
+    #[default]
+    None,
+}
+
+impl From<MatchTree> for Match {
+    fn from(tree: MatchTree) -> Self {
+        match tree {
+            MatchTree::Token(token) => Self::One(token),
+            MatchTree::Sequence(trees) => {
+                let mut matches = vec![];
+                for tree in trees {
+                    Match::from(tree).flatten_into(&mut matches);
+                }
+                Self::Many(matches)
+            },
+            MatchTree::Repetition(trees) if trees.is_empty() => Self::None,
+            MatchTree::Repetition(trees) => Self::Many(trees.into_iter().map(Self::from).collect()),
+            MatchTree::Separated(items, _) if items.is_empty() => Self::None,
+            MatchTree::Separated(items, separators) => {
+                let mut matches = vec![];
+                let mut separators = separators.into_iter();
+                for item in items {
+                    matches.push(item.into());
+                    if let Some(separator) = separators.next() {
+                        Match::from(separator).flatten_into(&mut matches);
+                    }
+                }
+                Self::Many(matches)
+            },
+            MatchTree::Choice(_, tree) | MatchTree::Group(_, _, tree) => (*tree).into(),
+            MatchTree::Optional(Some(tree)) => (*tree).into(),
+            MatchTree::Optional(None) | MatchTree::None => Self::None,
+        }
+    }
+}
+
+impl From<Match> for MatchTree {
+    fn from(m: Match) -> Self {
+        match m {
+            Match::One(token) => Self::Token(token),
+            Match::Many(matches) => Self::Sequence(matches.into_iter().map(Self::from).collect()),
+            Match::None => Self::None,
+        }
+    }
+}
+
+impl TryFrom<Match> for (MatchTree,) {
+    type Error = MacrosError;
+
+    fn try_from(m: Match) -> Result<Self, Self::Error> {
+        Ok((m.into(),))
+    }
+}
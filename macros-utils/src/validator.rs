@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+
+use proc_macro2::Span;
+
+use crate::{MacrosError, Match, ParseError, ParseErrorKind};
+
+/// A validator for a `{...}=` pattern, which is given the current output and the match of the
+/// pattern before it, and returns the new output along with an error if the match is invalid.
+/// See `validator` to create one from a function or a closure.
+///
+/// The closure can capture anything (like an `Rc` or a `Cell`), so a `Pattern` that holds a
+/// validator is neither `Send` nor `Sync`.
+pub type Validator<T> =
+    Box<dyn for<'a> Fn(Cow<'a, T>, &Match) -> (Result<(), MacrosError>, Cow<'a, T>)>;
+
+/// The result of a validator, which is either a message for the error (reported at the span of
+/// the validated match) or an error with its own span.
+pub trait ValidatorResult {
+    /// Convert the result into an error, with `span` for errors that do not have a span.
+    fn into_result(self, span: Span) -> Result<(), MacrosError>;
+}
+
+impl ValidatorResult for Result<(), String> {
+    fn into_result(self, span: Span) -> Result<(), MacrosError> {
+        self.map_err(|e| ParseError::new(span, ParseErrorKind::ValidatorFailed(e)).into())
+    }
+}
+
+impl ValidatorResult for Result<(), ParseError> {
+    fn into_result(self, _: Span) -> Result<(), MacrosError> {
+        self.map_err(MacrosError::from)
+    }
+}
+
+impl ValidatorResult for Result<(), MacrosError> {
+    fn into_result(self, _: Span) -> Result<(), MacrosError> {
+        self
+    }
+}
+
+/// Create a `Validator` from a function or a closure, which can capture its configuration and
+/// return any `ValidatorResult`.
+///
+/// # Example
+/// ```rs
+/// use macros_core::{validator, DynamicOutput, Match};
+///
+/// let max = 3;
+/// let validator = validator::<DynamicOutput, _>(move |output, m: &Match| match m {
+///     Match::Many(m) if m.len() > max => (Err(format!("at most {} tokens", max)), output),
+///     _ => (Ok(()), output),
+/// });
+/// ```
+pub fn validator<T, R>(
+    f: impl for<'a> Fn(Cow<'a, T>, &Match) -> (R, Cow<'a, T>) + 'static,
+) -> Validator<T>
+where
+    T: ToOwned<Owned = T>,
+    R: ValidatorResult,
+{
+    Box::new(move |output, m| validate(&f, output, m))
+}
+
+/// Run a validator (a function or a closure, see `validator`) on the output and the match of the
+/// pattern before it, which is how the parsers generated by `parser!` run `{...}=` patterns.
+pub fn validate<'a, T, R>(
+    f: impl for<'b> Fn(Cow<'b, T>, &Match) -> (R, Cow<'b, T>),
+    output: Cow<'a, T>,
+    m: &Match,
+) -> (Result<(), MacrosError>, Cow<'a, T>)
+where
+    T: ToOwned<Owned = T>,
+    R: ValidatorResult,
+{
+    let (res, output) = f(output, m);
+    (res.into_result(m.span()), output)
+}